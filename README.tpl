@@ -21,10 +21,12 @@ $ cargo install cargo-show-asm
 
   - Assembly in Intel or AT&T syntax.
   - Corresponding Rust source code alongside assembly.
-  - llvm-ir.
-  - rustc MIR
+  - llvm-ir, with Rust source code from debug locations.
+  - rustc MIR, with Rust source code from span comments on nightly
   - Wasm code
   - llvm-mca analysis
+  - Machine code disassembled from object files, libraries and executables, needs `disasm`
+    feature
 
 <USAGE>
 
@@ -76,6 +78,14 @@ instead of a full one and only matching functions will be listed
 $ cargo asm --lib Debug
 ```
 
+A source location can be used instead of a name to pick functions with code from that line,
+this works for assembly, llvm-ir and, with a nightly compiler, MIR
+
+```console,ignore
+$ cargo asm --lib src/opts.rs:120
+$ cargo asm --lib --llvm src/opts.rs:120
+```
+
 # My function isn't there!
 
 `rustc` will only generate the code for your function if it knows what type it is, including
@@ -118,6 +128,14 @@ cargo install cargo-show-asm -F bright-color
 cargo install cargo-show-asm -F dull-color
 ```
 
+# Disassembling object files
+
+`--disasm` and `--file` with object files, libraries and executables use a disassembler that
+needs a C compiler to build so it's not enabled by default
+```console
+cargo install cargo-show-asm -F disasm
+```
+
 # License
 This project is licensed under either of
 
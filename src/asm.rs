@@ -3,14 +3,31 @@ use crate::asm::statements::Label;
 use crate::cached_lines::CachedLines;
 use crate::demangle::LabelKind;
 use crate::{
-    color, demangle, esafeprintln, get_dump_range, interactive_mode, safeprintln, DumpRange, Item,
+    color, demangle, diff, dump_json, esafeprintln, get_all_matches, get_dump_range,
+    interactive_mode, safeprintln, DumpRange, Item,
 };
 // TODO, use https://sourceware.org/binutils/docs/as/index.html
-use crate::opts::{Format, ToDump};
+use crate::opts::{Format, Mode, Report, ToDump};
+use crate::report;
 
+mod calls;
+mod cfg;
+mod constants;
+mod dwarf;
+mod jumps;
+mod loops;
+mod panics;
+mod simd;
+mod source;
 mod statements;
+mod stats;
+mod vtables;
 
+pub use loops::InnerLoop;
+
+use anyhow::Context;
 use owo_colors::OwoColorize;
+use serde_json::json;
 use statements::{parse_statement, Directive, Loc, Statement};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet};
@@ -18,7 +35,7 @@ use std::io::Write;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 
-pub fn parse_file(input: &str) -> anyhow::Result<Vec<Statement>> {
+pub fn parse_file(input: &str) -> anyhow::Result<Vec<Statement<'_>>> {
     // eat all statements until the eof, so we can report the proper errors on failed parse
     match nom::multi::many0(parse_statement)(input) {
         Ok(("", stmts)) => Ok(stmts),
@@ -115,40 +132,95 @@ pub fn find_items(lines: &[Statement]) -> BTreeMap<Item, Range<usize>> {
     res
 }
 
+/// Number of actual instructions, directives, labels and comments are not included
+pub(crate) fn instruction_count(stmts: &[Statement]) -> usize {
+    stmts.iter().filter(|s| s.is_instruction()).count()
+}
+
 fn used_labels<'a>(stmts: &'_ [Statement<'a>]) -> BTreeSet<&'a str> {
-    stmts
-        .iter()
-        .filter_map(|stmt| match stmt {
-            Statement::Label(_) | Statement::Nothing => None,
-            Statement::Directive(dir) => match dir {
-                Directive::File(_)
-                | Directive::Loc(_)
-                | Directive::SubsectionsViaSym
-                | Directive::Set(_) => None,
-                Directive::Generic(g) => Some(g.0),
-                Directive::SectionStart(ss) => Some(*ss),
-            },
-            Statement::Instruction(i) => i.args,
-            Statement::Dunno(s) => Some(s),
-        })
+    label_references(stmts)
         .flat_map(crate::demangle::local_labels)
         .map(|m| m.as_str())
         .collect::<BTreeSet<_>>()
 }
 
+/// Names of local labels statements refer to, unlike [`used_labels`] without any separators
+/// in front of them
+fn used_label_names<'a>(stmts: &'_ [Statement<'a>]) -> BTreeSet<&'a str> {
+    label_references(stmts)
+        .flat_map(crate::demangle::local_label_names)
+        .collect::<BTreeSet<_>>()
+}
+
+/// Parts of statements that can refer to local labels
+fn label_references<'a, 'b>(stmts: &'b [Statement<'a>]) -> impl Iterator<Item = &'a str> + 'b {
+    stmts.iter().filter_map(|stmt| match stmt {
+        Statement::Label(_) | Statement::Nothing => None,
+        Statement::Directive(dir) => match dir {
+            Directive::File(_)
+            | Directive::Loc(_)
+            | Directive::SubsectionsViaSym
+            | Directive::Set(_) => None,
+            Directive::Generic(g) => Some(g.0),
+            Directive::SectionStart(ss) => Some(*ss),
+        },
+        Statement::Instruction(i) => i.args,
+        Statement::Dunno(s) => Some(s),
+    })
+}
+
 struct AsmDumpCtx<'a> {
     files: &'a BTreeMap<u64, (std::borrow::Cow<'a, Path>, Option<CachedLines>)>,
     fmt: &'a Format,
     stmts: &'a [Statement<'a>],
+    /// Inlining information for `--inlined`
+    inlined: Option<&'a dwarf::InlineInfo>,
+    /// Constant pool entries and string literals for `--constants`
+    constants: Option<&'a constants::Constants<'a>>,
+    /// Methods called through vtables for `--dyn-calls`, by statement index in the whole file
+    dyn_calls: Option<&'a BTreeMap<usize, String>>,
 }
 
-impl DumpRange for AsmDumpCtx<'_> {
-    fn dump_range_into_writer(
+impl AsmDumpCtx<'_> {
+    /// Inlining chain for the code after `.loc` at `ix`, `ix` is an index in the whole file
+    fn breadcrumb(&self, ix: usize, line: u64) -> Option<String> {
+        let inlined = self.inlined?;
+        let next = ix
+            + self.stmts[ix..]
+                .iter()
+                .position(Statement::is_instruction)?;
+        let (root, chain) = inlined.chain(next)?;
+        Some(dwarf::breadcrumb(root, &chain, line, self.fmt.full_name))
+    }
+
+    /// Value of a constant or a string literal the instruction refers to
+    fn constant(&self, stmt: &Statement) -> Option<String> {
+        match stmt {
+            Statement::Instruction(instr) => self.constants?.describe(instr, self.fmt.full_name),
+            _ => None,
+        }
+    }
+
+    /// Method called through a vtable by instruction at `ix`, `ix` is an index in the whole file
+    fn dyn_call(&self, ix: usize) -> Option<&str> {
+        self.dyn_calls?.get(&ix).map(String::as_str)
+    }
+}
+
+impl AsmDumpCtx<'_> {
+    /// Write statements from `range`, returns output lines statements are printed on
+    ///
+    /// `loops` are loop marks for `--loops`, computed for the same range
+    fn write_statements(
         &self,
         range: Option<Range<usize>>,
         writer: &mut impl Write,
-    ) -> anyhow::Result<()> {
-        let &Self { files, fmt, stmts } = self;
+        loops: Option<&loops::LoopMarks>,
+    ) -> anyhow::Result<BTreeMap<usize, usize>> {
+        let &Self {
+            files, fmt, stmts, ..
+        } = self;
+        let offset = range.as_ref().map_or(0, |r| r.start);
         let stmts = range.map_or(stmts, |r| &stmts[r]);
 
         let mut prev_loc = Loc::default();
@@ -159,8 +231,10 @@ impl DumpRange for AsmDumpCtx<'_> {
             used_labels(stmts)
         };
 
+        let mut writer = jumps::CountLines::new(writer);
+        let mut rows = BTreeMap::new();
         let mut empty_line = false;
-        for line in stmts.iter() {
+        for (ix, line) in stmts.iter().enumerate() {
             if fmt.verbosity > 2 {
                 writeln!(writer, "{line:?}")?;
             }
@@ -186,6 +260,10 @@ impl DumpRange for AsmDumpCtx<'_> {
                             "\t\t{}",
                             color!(rust_line.trim_start(), OwoColorize::bright_red)
                         )?;
+                        if let Some(crumb) = self.breadcrumb(offset + ix, loc.line) {
+                            let crumb = format!("\t\t// {crumb}");
+                            writeln!(writer, "{}", color!(crumb, OwoColorize::cyan))?;
+                        }
                     }
                     Some((fname, None)) => {
                         if fmt.verbosity > 0 {
@@ -213,7 +291,13 @@ impl DumpRange for AsmDumpCtx<'_> {
             }) = line
             {
                 if fmt.keep_labels || used.contains(id) {
-                    writeln!(writer, "{line}")?;
+                    rows.insert(ix, writer.lines);
+                    write!(writer, "{line}")?;
+                    if let Some(depth) = loops.and_then(|l| l.header(ix)) {
+                        let mark = format!("\t# loop, depth {depth}");
+                        write!(writer, "{}", color!(mark, OwoColorize::magenta))?;
+                    }
+                    writeln!(writer)?;
                 } else if !empty_line && *kind != LabelKind::Temp {
                     writeln!(writer)?;
                     empty_line = true;
@@ -224,15 +308,145 @@ impl DumpRange for AsmDumpCtx<'_> {
                 }
 
                 empty_line = false;
+                rows.insert(ix, writer.lines);
                 #[allow(clippy::match_bool)]
                 match fmt.full_name {
-                    true => writeln!(writer, "{line:#}")?,
-                    false => writeln!(writer, "{line}")?,
+                    true => write!(writer, "{line:#}")?,
+                    false => write!(writer, "{line}")?,
+                }
+                if let Some(value) = self.constant(line) {
+                    let value = format!("\t# {value}");
+                    write!(writer, "{}", color!(value, OwoColorize::blue))?;
                 }
+                if let Some(method) = self.dyn_call(offset + ix) {
+                    let method = format!("\t# {method}");
+                    write!(writer, "{}", color!(method, OwoColorize::blue))?;
+                }
+                writeln!(writer)?;
             }
         }
+        Ok(rows)
+    }
+}
+
+impl DumpRange for AsmDumpCtx<'_> {
+    fn dump_range_into_writer(
+        &self,
+        range: Option<Range<usize>>,
+        writer: &mut impl Write,
+    ) -> anyhow::Result<()> {
+        let fmt = self.fmt;
+        if !(fmt.visualize_jumps || fmt.loops) {
+            self.write_statements(range, writer, None)?;
+            return Ok(());
+        }
+        let stmts = range.clone().map_or(self.stmts, |r| &self.stmts[r]);
+        let loops = fmt.loops.then(|| loops::LoopMarks::new(stmts));
+        let mut buf = Vec::new();
+        let rows = self.write_statements(range, &mut buf, loops.as_ref())?;
+        if fmt.visualize_jumps {
+            let arrows = jumps::find_arrows(stmts, &rows);
+            let mut with_arrows = Vec::new();
+            jumps::write_with_arrows(&mut with_arrows, &String::from_utf8(buf)?, &arrows)?;
+            buf = with_arrows;
+        }
+        match loops {
+            Some(loops) => loops.write_with_margin(writer, &String::from_utf8(buf)?, &rows)?,
+            None => writer.write_all(&buf)?,
+        }
         Ok(())
     }
+
+    fn range_to_json(&self, range: Option<Range<usize>>) -> anyhow::Result<serde_json::Value> {
+        let &Self {
+            files, fmt, stmts, ..
+        } = self;
+        let offset = range.as_ref().map_or(0, |r| r.start);
+        let stmts = range.map_or(stmts, |r| &stmts[r]);
+
+        let used = if fmt.keep_labels {
+            BTreeSet::new()
+        } else {
+            used_labels(stmts)
+        };
+
+        let mut source = serde_json::Value::Null;
+        let mut res = Vec::new();
+        for (ix, line) in stmts.iter().enumerate() {
+            match line {
+                Statement::Directive(Directive::Loc(loc)) => {
+                    source = if loc.line == 0 {
+                        serde_json::Value::Null
+                    } else {
+                        let mut source = loc_to_json(files, loc);
+                        if let Some(crumb) = self.breadcrumb(offset + ix, loc.line) {
+                            source["inlined"] = json!(crumb);
+                        }
+                        source
+                    };
+                }
+                Statement::Directive(Directive::File(_)) | Statement::Nothing => {}
+                Statement::Label(Label {
+                    kind: LabelKind::Local | LabelKind::Temp,
+                    id,
+                }) if !(fmt.keep_labels || used.contains(id)) => {}
+                Statement::Label(label) => res.push(json!({
+                    "kind": "label",
+                    "id": label.id,
+                    "name": demangle::contents(label.id, fmt.full_name),
+                })),
+                Statement::Instruction(instr) => {
+                    let mut instr_json = json!({
+                        "kind": "instruction",
+                        "op": instr.op,
+                        "args": instr.args.map(|args| demangle::contents(args, fmt.full_name)),
+                        "source": source,
+                    });
+                    if let Some(value) = self.constant(line) {
+                        instr_json["constant"] = json!(value);
+                    }
+                    if let Some(method) = self.dyn_call(offset + ix) {
+                        instr_json["dyn_call"] = json!(method);
+                    }
+                    res.push(instr_json);
+                }
+                Statement::Directive(dir) if !fmt.simplify => {
+                    let text = if fmt.full_name {
+                        format!("{dir:#}")
+                    } else {
+                        format!("{dir}")
+                    };
+                    res.push(json!({ "kind": "directive", "text": text.trim_start() }));
+                }
+                Statement::Dunno(text) if !fmt.simplify => {
+                    res.push(json!({ "kind": "unknown", "text": text }));
+                }
+                Statement::Directive(_) | Statement::Dunno(_) => {}
+            }
+        }
+        Ok(json!({ "statements": res }))
+    }
+}
+
+fn loc_to_json(
+    files: &BTreeMap<u64, (Cow<'_, Path>, Option<CachedLines>)>,
+    loc: &Loc,
+) -> serde_json::Value {
+    let (file, rust) = match files.get(&loc.file) {
+        Some((fname, lines)) => (
+            Some(fname.display().to_string()),
+            lines
+                .as_ref()
+                .and_then(|lines| lines.get(loc.line as usize - 1)),
+        ),
+        None => (None, None),
+    };
+    json!({
+        "file": file,
+        "line": loc.line,
+        "column": loc.column,
+        "rust": rust.map(str::trim_start),
+    })
 }
 
 // DWARF information contains references to souce files
@@ -247,7 +461,7 @@ impl DumpRange for AsmDumpCtx<'_> {
 //    Some examples:
 //        /cargo/registry/src/github.com-1ecc6299db9ec823/hashbrown-0.12.3/src/raw/bitmask.rs
 //        /Users/runner/.cargo/registry/src/github.com-1ecc6299db9ec823/hashbrown-0.12.3/src/map.rs
-fn locate_sources(sysroot: &Path, path: &Path) -> Option<PathBuf> {
+pub(crate) fn locate_sources(sysroot: &Path, path: &Path) -> Option<PathBuf> {
     // a real file that simply exists
     if path.exists() {
         return Some(path.into());
@@ -258,7 +472,7 @@ fn locate_sources(sysroot: &Path, path: &Path) -> Option<PathBuf> {
             "You need to install rustc sources to be able to see the rust annotations, try\n\
                                        \trustup component add rust-src"
         );
-        std::process::exit(1);
+        crate::exit(1);
     };
 
     // rust sources, Linux style
@@ -343,44 +557,941 @@ fn load_rust_sources<'a>(
     }
 }
 
+/// Report calls to panicking functions in an item, exits with an error if there are any
+fn dump_panics(
+    item: Option<(Item, Range<usize>)>,
+    statements: &[Statement],
+    files: &BTreeMap<u64, (Cow<'_, Path>, Option<CachedLines>)>,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let (item, stmts) = match &item {
+        Some((item, range)) => (Some(item), &statements[range.clone()]),
+        None => (None, statements),
+    };
+    let panics = panics::find_panics(stmts);
+
+    if fmt.json {
+        let panics = panics
+            .iter()
+            .map(|p| {
+                let source = p
+                    .loc
+                    .map_or(serde_json::Value::Null, |loc| loc_to_json(files, &loc));
+                json!({ "name": p.name, "source": source })
+            })
+            .collect::<Vec<_>>();
+        dump_json(item, json!({ "panics": panics }))?;
+    } else if panics.is_empty() {
+        safeprintln!("No calls to panicking functions found");
+    } else {
+        safeprintln!("Found {} calls to panicking functions", panics.len());
+        for panic in &panics {
+            safeprintln!("{}", color!(panic.name, OwoColorize::green));
+            match panic.loc.and_then(|loc| Some((loc, files.get(&loc.file)?))) {
+                Some((loc, (fname, lines))) => {
+                    let pos = format!("\t// {} : {}", fname.display(), loc.line);
+                    safeprintln!("{}", color!(pos, OwoColorize::cyan));
+                    if let Some(line) = lines
+                        .as_ref()
+                        .and_then(|lines| lines.get(loc.line as usize - 1))
+                    {
+                        safeprintln!("\t{}", color!(line.trim_start(), OwoColorize::bright_red));
+                    }
+                }
+                None => safeprintln!("\t{}", color!("// unknown location", OwoColorize::cyan)),
+            }
+        }
+    }
+
+    if !panics.is_empty() {
+        crate::exit(1);
+    }
+    Ok(())
+}
+
+/// Print instruction statistics for every item, or for the whole file if `items` is empty
+fn dump_stats(
+    items: Vec<(Item, Range<usize>)>,
+    functions: &BTreeMap<Item, Range<usize>>,
+    statements: &[Statement],
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let stats = if items.is_empty() {
+        vec![(
+            None,
+            stats::Stats::new(statements, functions.values().cloned()),
+        )]
+    } else {
+        items
+            .into_iter()
+            .map(|(item, range)| {
+                let stats = stats::Stats::new(statements, std::iter::once(range));
+                (Some(item), stats)
+            })
+            .collect()
+    };
+
+    if fmt.json {
+        let items = stats
+            .iter()
+            .map(|(item, stats)| {
+                json!({
+                    "item": item.as_ref().map(Item::to_json),
+                    "instructions": stats.instructions,
+                    "loads": stats.loads,
+                    "stores": stats.stores,
+                    "branches": stats.branches,
+                    "calls": stats.calls,
+                    "stack_adjustments": stats.stack_adjustments,
+                    "frame_size": stats.frame_size,
+                    "opcodes": stats.opcodes,
+                })
+            })
+            .collect::<Vec<_>>();
+        return dump_json(None, json!({ "items": items }));
+    }
+
+    for (ix, (item, stats)) in stats.iter().enumerate() {
+        if ix > 0 {
+            safeprintln!();
+        }
+        if let Some(item) = item {
+            let name = if fmt.full_name {
+                &item.hashed
+            } else {
+                &item.name
+            };
+            let header = format!("==> {name} <==");
+            safeprintln!("{}", color!(header, OwoColorize::bright_black));
+        }
+        safeprintln!("Instructions: {}", stats.instructions);
+        safeprintln!("Loads: {}, stores: {}", stats.loads, stats.stores);
+        safeprintln!("Branches: {}, calls: {}", stats.branches, stats.calls);
+        let frame = if item.is_some() {
+            "stack frame"
+        } else {
+            "largest stack frame"
+        };
+        safeprintln!(
+            "Stack adjustments: {}, {frame}: {} bytes",
+            stats.stack_adjustments,
+            stats.frame_size
+        );
+        let histogram = stats.histogram();
+        let width = histogram.iter().map(|(op, _)| op.len()).max().unwrap_or(0);
+        let most = histogram.first().map_or(1, |(_, count)| *count);
+        for (op, count) in histogram {
+            let bar = "█".repeat((count * 40 + most - 1) / most);
+            safeprintln!(
+                "{op:>width$} {count:>6} {}",
+                color!(bar, OwoColorize::green)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print how much of an item, or of the whole file, uses vector instructions
+fn dump_simd_report(
+    item: Option<(Item, Range<usize>)>,
+    functions: &BTreeMap<Item, Range<usize>>,
+    statements: &[Statement],
+    files: &BTreeMap<u64, (Cow<'_, Path>, Option<CachedLines>)>,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let (item, report) = match item {
+        Some((item, range)) => (Some(item), simd::report(statements, std::iter::once(range))),
+        None => (None, simd::report(statements, functions.values().cloned())),
+    };
+    let total = report.total();
+    let vector = report.vector();
+
+    if fmt.json {
+        let classes = report
+            .classes
+            .iter()
+            .map(|(class, count)| (class.to_string(), json!(count)))
+            .collect::<serde_json::Map<_, _>>();
+        let scalar_in_loops = report
+            .scalar_in_loops
+            .iter()
+            .map(|(loc, count)| json!({ "source": loc_to_json(files, loc), "count": count }))
+            .collect::<Vec<_>>();
+        let body = json!({
+            "instructions": total,
+            "vector": vector,
+            "widest": report.widest().map(|c| c.to_string()),
+            "classes": classes,
+            "scalar_in_loops": scalar_in_loops,
+        });
+        return dump_json(item.as_ref(), body);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let percent = if total == 0 {
+        0.0
+    } else {
+        vector as f64 * 100.0 / total as f64
+    };
+    safeprintln!("Instructions: {total}, vector: {vector} ({percent:.1}%)");
+    match report.widest() {
+        Some(widest) => safeprintln!("Widest vector registers: {widest}"),
+        None => safeprintln!("No vector registers used"),
+    }
+    for (class, count) in &report.classes {
+        safeprintln!("\t{class}: {count}");
+    }
+    if report.scalar_in_loops.is_empty() {
+        return Ok(());
+    }
+    safeprintln!("Scalar instructions inside of loops:");
+    for (loc, count) in &report.scalar_in_loops {
+        let Some((fname, lines)) = files.get(&loc.file) else {
+            continue;
+        };
+        let pos = format!("\t// {} : {}", fname.display(), loc.line);
+        safeprintln!("{} ({count})", color!(pos, OwoColorize::cyan));
+        if let Some(line) = lines
+            .as_ref()
+            .and_then(|lines| lines.get(loc.line as usize - 1))
+        {
+            safeprintln!("\t{}", color!(line.trim_start(), OwoColorize::bright_red));
+        }
+    }
+    Ok(())
+}
+
+/// List functions called by an item or calling it, depending on `fmt`
+fn dump_calls(
+    item: Option<Item>,
+    graph: &[(&Item, Vec<calls::Callee>)],
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let Some(item) = item else {
+        anyhow::bail!("--callees and --callers need a single function");
+    };
+    let defined = graph
+        .iter()
+        .map(|(item, _)| item.hashed.as_str())
+        .collect::<BTreeSet<_>>();
+
+    let (title, names) = if fmt.mode == Some(Mode::Callers) {
+        let callers = graph
+            .iter()
+            .filter(|(_, callees)| callees.iter().any(|c| c.hashed == item.hashed))
+            .map(|(caller, _)| (caller.hashed.as_str(), caller.name.as_str()))
+            .collect::<Vec<_>>();
+        ("callers", callers)
+    } else {
+        let callees = graph
+            .iter()
+            .find(|(i, _)| i.hashed == item.hashed)
+            .map(|(_, callees)| callees.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .map(|c| (c.hashed.as_str(), c.name.as_str()))
+            .collect::<Vec<_>>();
+        ("callees", callees)
+    };
+
+    if fmt.json {
+        let names = names
+            .iter()
+            .map(|(hashed, name)| {
+                json!({ "name": name, "hashed": hashed, "defined": defined.contains(hashed) })
+            })
+            .collect::<Vec<_>>();
+        return dump_json(Some(&item), json!({ title: names }));
+    }
+
+    if names.is_empty() {
+        safeprintln!("No {title} found for {}", item.name);
+    }
+    for (hashed, name) in names {
+        let name = if fmt.full_name { hashed } else { name };
+        if defined.contains(hashed) {
+            safeprintln!("{}", color!(name, OwoColorize::green));
+        } else {
+            safeprintln!(
+                "{} {}",
+                color!(name, OwoColorize::green),
+                color!("(external)", OwoColorize::bright_black)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print all the vtables defined in the file with their slots
+fn dump_vtables(statements: &[Statement], fmt: &Format) -> anyhow::Result<()> {
+    let constants = constants::Constants::new(statements);
+    let vtables = vtables::find_vtables(&constants);
+    let name = |sym: Option<&str>| sym.map(|s| vtables::symbol_name(s, fmt.full_name));
+
+    if fmt.json {
+        let vtables = vtables
+            .iter()
+            .map(|vtable| {
+                json!({
+                    "name": vtable.name(),
+                    "label": vtable.label,
+                    "drop_in_place": name(vtable.drop),
+                    "size": vtable.size,
+                    "align": vtable.align,
+                    "methods": vtable.methods.iter().map(|m| name(*m)).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        return dump_json(None, json!({ "vtables": vtables }));
+    }
+
+    if vtables.is_empty() {
+        safeprintln!("No vtables found");
+    }
+    for vtable in &vtables {
+        safeprintln!(
+            "{} {}",
+            color!(vtable.name(), OwoColorize::green),
+            color!(vtable.label, OwoColorize::bright_black)
+        );
+        safeprintln!("    size: {}, align: {}", vtable.size, vtable.align);
+        let slots = std::iter::once(vtable.drop).chain(vtable.methods.iter().copied());
+        for (ix, slot) in slots.enumerate() {
+            // size and alignment live between drop_in_place and the first method
+            let offset = if ix == 0 { 0 } else { (ix + 2) * 8 };
+            let name = name(slot).unwrap_or_else(|| String::from("-"));
+            safeprintln!("    {offset:>4} {}", color!(name, OwoColorize::bright_blue));
+        }
+    }
+    Ok(())
+}
+
+/// Print Rust sources of an item top to bottom with instructions grouped under each line
+///
+/// Lines of the function itself come first, followed by lines inlined from elsewhere
+/// and instructions without any location
+fn dump_by_source(
+    item: Option<(Item, Range<usize>)>,
+    statements: &[Statement],
+    files: &BTreeMap<u64, (Cow<'_, Path>, Option<CachedLines>)>,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let (item, stmts) = match &item {
+        Some((item, range)) => (Some(item), &statements[range.clone()]),
+        None => (None, statements),
+    };
+    let by_line = source::ByLine::new(stmts);
+    let render = |stmt: &Statement| match fmt.full_name {
+        true => format!("{stmt:#}"),
+        false => format!("{stmt}"),
+    };
+    let rust_line = |file: u64, line: u64| {
+        files
+            .get(&file)
+            .and_then(|(_, lines)| lines.as_ref()?.get(line as usize - 1))
+    };
+
+    // function itself is shown in full, inlined code only shows lines with instructions
+    let mut lines = Vec::new();
+    let main_span = by_line.main_span();
+    if let Some((file, span)) = &main_span {
+        lines.extend(span.clone().map(|line| (*file, line)));
+    }
+    lines.extend(
+        by_line
+            .lines
+            .keys()
+            .filter(|(file, line)| {
+                main_span
+                    .as_ref()
+                    .map_or(true, |(f, span)| f != file || !span.contains(line))
+            })
+            .copied(),
+    );
+
+    if fmt.json {
+        let lines = lines
+            .iter()
+            .map(|&(file, line)| {
+                let instructions = by_line
+                    .lines
+                    .get(&(file, line))
+                    .into_iter()
+                    .flatten()
+                    .map(|stmt| render(stmt).trim_start().to_owned())
+                    .collect::<Vec<_>>();
+                json!({
+                    "file": files.get(&file).map(|(path, _)| path.display().to_string()),
+                    "line": line,
+                    "rust": rust_line(file, line).map(str::trim_end),
+                    "instructions": instructions,
+                })
+            })
+            .collect::<Vec<_>>();
+        let unknown = by_line
+            .unknown
+            .iter()
+            .map(|stmt| render(stmt).trim_start().to_owned())
+            .collect::<Vec<_>>();
+        return dump_json(item, json!({ "lines": lines, "unknown": unknown }));
+    }
+
+    let mut prev_file = None;
+    for (file, line) in lines {
+        if prev_file != Some(file) {
+            if prev_file.is_some() {
+                safeprintln!();
+            }
+            let name = files
+                .get(&file)
+                .map_or_else(|| format!("file #{file}"), |f| f.0.display().to_string());
+            safeprintln!("{}", color!(format!("// {name}"), OwoColorize::cyan));
+            prev_file = Some(file);
+        }
+        let instrs = by_line.lines.get(&(file, line));
+        let count = instrs.map_or(String::new(), |i| format!("[{}]", i.len()));
+        safeprintln!(
+            "{:>6} {:>6} {}",
+            color!(line, OwoColorize::cyan),
+            color!(count, OwoColorize::cyan),
+            color!(rust_line(file, line).unwrap_or(""), OwoColorize::bright_red)
+        );
+        for stmt in instrs.into_iter().flatten() {
+            safeprintln!("{}", render(stmt));
+        }
+    }
+    if !by_line.unknown.is_empty() {
+        safeprintln!();
+        let header = format!("// no location [{}]", by_line.unknown.len());
+        safeprintln!("{}", color!(header, OwoColorize::cyan));
+        for stmt in &by_line.unknown {
+            safeprintln!("{}", render(stmt));
+        }
+    }
+    Ok(())
+}
+
+/// Print control flow graph of an item as Graphviz DOT or as JSON
+fn dump_cfg(
+    item: Option<(Item, Range<usize>)>,
+    statements: &[Statement],
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let (item, stmts) = match &item {
+        Some((item, range)) => (Some(item), &statements[range.clone()]),
+        None => (None, statements),
+    };
+    let graph = cfg::Cfg::new(stmts);
+
+    if fmt.json {
+        let blocks = graph
+            .blocks
+            .iter()
+            .map(|block| {
+                let instructions = stmts[block.range.clone()]
+                    .iter()
+                    .filter(|stmt| stmt.is_instruction())
+                    .map(|stmt| match fmt.full_name {
+                        true => format!("{stmt:#}").trim_start().to_owned(),
+                        false => format!("{stmt}").trim_start().to_owned(),
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "labels": block.labels, "instructions": instructions })
+            })
+            .collect::<Vec<_>>();
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| json!({ "from": e.from, "to": e.to, "kind": e.kind.name() }))
+            .collect::<Vec<_>>();
+        return dump_json(item, json!({ "blocks": blocks, "edges": edges }));
+    }
+
+    let title = item.map_or("everything", |item| item.name.as_str());
+    let mut writer = std::io::stdout();
+    if graph
+        .write_dot(&mut writer, title, stmts, fmt.full_name)
+        .is_err()
+        || writer.flush().is_err()
+    {
+        crate::exit(0); // Exit when stdout is closed
+    }
+    Ok(())
+}
+
 /// try to print `goal` from `path`, collect available items otherwise
 pub fn dump_function(
     goal: ToDump,
     path: &Path,
     sysroot: &Path,
     fmt: &Format,
+) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)?;
+    dump_contents(goal, &contents, sysroot, fmt)
+}
+
+/// Same as [`dump_function`] for assembly that is already loaded
+///
+/// # Errors
+/// Reports parsing errors
+pub fn dump_contents(
+    goal: ToDump,
+    contents: &str,
+    sysroot: &Path,
+    fmt: &Format,
 ) -> anyhow::Result<()> {
     if fmt.verbosity > 2 {
         safeprintln!("goal: {goal:?}");
     }
 
-    let contents = std::fs::read_to_string(path)?;
-    let statements = parse_file(&contents)?;
+    let statements = parse_file(contents)?;
     let functions = find_items(&statements);
 
     if fmt.verbosity > 2 {
         safeprintln!("{functions:?}");
     }
 
+    if let ToDump::Report { report, sort_by } = goal {
+        match report {
+            Report::Sizes => {
+                let sizes = functions
+                    .iter()
+                    .map(|(item, range)| (item, instruction_count(&statements[range.clone()])));
+                return report::dump_sizes(sizes, sort_by, fmt);
+            }
+            Report::Calls => {
+                let graph = calls::call_graph(&statements, &functions);
+                if fmt.json {
+                    let functions = graph
+                        .iter()
+                        .map(|(item, callees)| {
+                            let callees = callees.iter().map(|c| &c.hashed).collect::<Vec<_>>();
+                            let mut item = item.to_json();
+                            item["callees"] = json!(callees);
+                            item
+                        })
+                        .collect::<Vec<_>>();
+                    return dump_json(None, json!({ "functions": functions }));
+                }
+                let mut writer = std::io::stdout();
+                if calls::write_dot(&mut writer, &graph, fmt.full_name).is_err()
+                    || writer.flush().is_err()
+                {
+                    crate::exit(0); // Exit when stdout is closed
+                }
+                return Ok(());
+            }
+            Report::Vtables => return dump_vtables(&statements, fmt),
+        }
+    }
+
+    let (goal, functions) = select_by_location(goal, &statements, functions);
+
     let mut files = BTreeMap::new();
-    if fmt.rust {
+    if fmt.rust || fmt.mode == Some(Mode::BySource) {
         load_rust_sources(sysroot, &statements, fmt, &mut files);
+    } else {
+        // file names are still useful for JSON and reports even without the sources
+        for line in &statements {
+            if let Statement::Directive(Directive::File(f)) = line {
+                files
+                    .entry(f.index)
+                    .or_insert_with(|| (f.path.as_full_path(), None));
+            }
+        }
     }
 
+    let inlined = fmt.inlined.then(|| dwarf::InlineInfo::new(&statements));
+    if fmt.verbosity > 0 && inlined.as_ref().is_some_and(dwarf::InlineInfo::is_empty) {
+        safeprintln!("No inlining information found in debug info");
+    }
+    let constants =
+        (fmt.constants || fmt.dyn_calls).then(|| constants::Constants::new(&statements));
+    let dyn_calls = constants
+        .as_ref()
+        .filter(|_| fmt.dyn_calls)
+        .map(|constants| {
+            let vtables = vtables::find_vtables(constants);
+            vtables::annotate_calls(&statements, &vtables, fmt.full_name)
+        });
     let dump_ctx = AsmDumpCtx {
         files: &files,
         fmt,
         stmts: &statements,
+        inlined: inlined.as_ref(),
+        constants: constants.as_ref().filter(|_| fmt.constants),
+        dyn_calls: dyn_calls.as_ref(),
     };
 
     if matches!(goal, ToDump::Interactive) {
         interactive_mode(&functions, dump_ctx);
+        return Ok(());
+    }
+    match fmt.mode {
+        Some(Mode::Stats) => {
+            let items = match get_all_matches(&goal, &functions) {
+                Some(matches) => matches,
+                None => get_dump_range(goal, fmt, functions.clone())
+                    .into_iter()
+                    .collect(),
+            };
+            dump_stats(items, &functions, &statements, fmt)
+        }
+        Some(Mode::Panics) => dump_panics(
+            get_dump_range(goal, fmt, functions),
+            &statements,
+            &files,
+            fmt,
+        ),
+        Some(Mode::SimdReport) => dump_simd_report(
+            get_dump_range(goal, fmt, functions.clone()),
+            &functions,
+            &statements,
+            &files,
+            fmt,
+        ),
+        Some(Mode::Cfg) => dump_cfg(get_dump_range(goal, fmt, functions), &statements, fmt),
+        Some(Mode::BySource) => dump_by_source(
+            get_dump_range(goal, fmt, functions),
+            &statements,
+            &files,
+            fmt,
+        ),
+        Some(Mode::Callees | Mode::Callers) => {
+            let graph = calls::call_graph(&statements, &functions);
+            let item = get_dump_range(goal, fmt, functions.clone());
+            dump_calls(item.map(|i| i.0), &graph, fmt)
+        }
+        None => dump_code(&dump_ctx, goal, functions),
+    }
+}
+
+/// Dump the code itself: every match, a function with its callees, inner loops of a function
+/// or a single function
+fn dump_code(
+    dump_ctx: &AsmDumpCtx,
+    goal: ToDump,
+    functions: BTreeMap<Item, Range<usize>>,
+) -> anyhow::Result<()> {
+    let fmt = dump_ctx.fmt;
+    if let Some(matches) = get_all_matches(&goal, &functions) {
+        dump_ctx.dump_items(fmt, matches)?;
+    } else if let ToDump::Function {
+        with_callees: Some(depth),
+        ..
+    } = goal
+    {
+        let item = get_dump_range(goal, fmt, functions.clone());
+        dump_with_callees(dump_ctx, item, &functions, depth)?;
+    } else if fmt.inner_loops {
+        dump_inner_loops(dump_ctx, get_dump_range(goal, fmt, functions))?;
     } else {
         let range = get_dump_range(goal, fmt, functions);
         if fmt.verbosity > 0 && range.is_none() {
             safeprintln!("Going to print the whole file");
         }
-        dump_ctx.dump_range(range)?;
+        dump_ctx.dump_item(fmt, range)?;
     }
     Ok(())
 }
+
+/// Dump an item followed by functions from the same file it calls, up to `depth` calls deep
+fn dump_with_callees(
+    dump_ctx: &AsmDumpCtx,
+    item: Option<(Item, Range<usize>)>,
+    functions: &BTreeMap<Item, Range<usize>>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    let fmt = dump_ctx.fmt;
+    let Some((item, range)) = item else {
+        return dump_ctx.dump_item(fmt, None);
+    };
+    let callees = calls::local_callees(&item, dump_ctx.stmts, functions, depth);
+
+    if fmt.json {
+        let mut body = dump_ctx.range_to_json(Some(range))?;
+        let callees = callees
+            .into_iter()
+            .map(|(callee, range)| {
+                let mut callee_body = dump_ctx.range_to_json(Some(range))?;
+                callee_body["item"] = callee.to_json();
+                Ok(callee_body)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        body["callees"] = json!(callees);
+        return dump_json(Some(&item), body);
+    }
+
+    // same headers as with --all-matches so it's clear where every function starts
+    let callees = callees
+        .into_iter()
+        .map(|(callee, range)| (callee.clone(), range));
+    let items = std::iter::once((item, range)).chain(callees).collect();
+    dump_ctx.dump_items(fmt, items)
+}
+
+/// Innermost loops of a function in `range`, with statement ranges relative to `stmts`
+#[must_use]
+pub fn inner_loops<'a>(stmts: &[Statement<'a>], range: Range<usize>) -> Vec<InnerLoop<'a>> {
+    let mut loops = loops::inner_loops(&stmts[range.clone()]);
+    for r in loops.iter_mut().flat_map(|lp| &mut lp.ranges) {
+        *r = range.start + r.start..range.start + r.end;
+    }
+    loops
+}
+
+/// Dump innermost loops of an item, each loop is printed after a header with its label
+///
+/// Blocks of a loop are not always adjacent, loops can span several ranges
+fn dump_inner_loops(
+    dump_ctx: &AsmDumpCtx,
+    item: Option<(Item, Range<usize>)>,
+) -> anyhow::Result<()> {
+    let fmt = dump_ctx.fmt;
+    let Some((item, range)) = item else {
+        return dump_ctx.dump_item(fmt, None);
+    };
+    let loops = inner_loops(dump_ctx.stmts, range);
+
+    if fmt.json {
+        let loops = loops
+            .iter()
+            .map(|lp| {
+                let mut statements = Vec::new();
+                for r in &lp.ranges {
+                    let body = dump_ctx.range_to_json(Some(r.clone()))?;
+                    if let serde_json::Value::Array(stmts) = &body["statements"] {
+                        statements.extend(stmts.iter().cloned());
+                    }
+                }
+                Ok(json!({ "header": lp.header, "depth": lp.depth, "statements": statements }))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        return dump_json(Some(&item), json!({ "loops": loops }));
+    }
+
+    if loops.is_empty() {
+        safeprintln!("No loops found in {}", item.name);
+    }
+    for (ix, lp) in loops.iter().enumerate() {
+        if ix > 0 {
+            safeprintln!();
+        }
+        let title = format!("# loop at {}, depth {}", lp.header, lp.depth);
+        safeprintln!("{}", color!(title, OwoColorize::magenta));
+        for r in &lp.ranges {
+            dump_ctx.dump_range(Some(r.clone()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Narrow down functions to ones with code from a source location passed instead of a name
+#[must_use]
+pub fn select_by_location(
+    goal: ToDump,
+    stmts: &[Statement],
+    functions: BTreeMap<Item, Range<usize>>,
+) -> (ToDump, BTreeMap<Item, Range<usize>>) {
+    crate::select_by_location(goal, functions, |functions, path, line| {
+        source::items_for_line(stmts, functions, path, line)
+            .into_iter()
+            .cloned()
+            .collect()
+    })
+}
+
+/// Render a range from the file without colors, used to compare builds
+fn render_plain(
+    sysroot: &Path,
+    statements: &[Statement],
+    fmt: &Format,
+    range: Option<Range<usize>>,
+) -> anyhow::Result<String> {
+    let mut files = BTreeMap::new();
+    if fmt.rust {
+        load_rust_sources(sysroot, statements, fmt, &mut files);
+    }
+    let inlined = fmt.inlined.then(|| dwarf::InlineInfo::new(statements));
+    let constants = (fmt.constants || fmt.dyn_calls).then(|| constants::Constants::new(statements));
+    let dyn_calls = constants
+        .as_ref()
+        .filter(|_| fmt.dyn_calls)
+        .map(|constants| {
+            let vtables = vtables::find_vtables(constants);
+            vtables::annotate_calls(statements, &vtables, fmt.full_name)
+        });
+    let dump_ctx = AsmDumpCtx {
+        files: &files,
+        fmt,
+        stmts: statements,
+        inlined: inlined.as_ref(),
+        constants: constants.as_ref().filter(|_| fmt.constants),
+        dyn_calls: dyn_calls.as_ref(),
+    };
+    let mut buf = Vec::new();
+    dump_ctx.dump_range_into_writer(range, &mut buf)?;
+    Ok(diff::normalize(&String::from_utf8(buf)?))
+}
+
+/// Compare `goal` between two builds
+///
+/// `goal` is picked from the first file, matching function in the second one is
+/// picked by the demangled name
+/// Ranges of the function to compare in both builds, `None` to compare the whole files
+///
+/// The function is picked in the first build, by name or by source location, and looked up by
+/// name in the second one
+fn diff_ranges(
+    goal: ToDump,
+    fmt: &Format,
+    first: &[Statement],
+    second: &[Statement],
+) -> anyhow::Result<Option<[Range<usize>; 2]>> {
+    let (goal, items) = select_by_location(goal, first, find_items(first));
+    let Some((item, range)) = get_dump_range(goal, fmt, items) else {
+        return Ok(None);
+    };
+    let items = find_items(second);
+    let same_name = |i: &&Item| i.name == item.name;
+    let other = items
+        .keys()
+        .filter(same_name)
+        .find(|i| i.index == item.index)
+        .or_else(|| items.keys().find(same_name))
+        .with_context(|| format!("{:?} is not present in the second build", item.name))?;
+    Ok(Some([range, items[other].clone()]))
+}
+
+pub fn diff_function(
+    goal: ToDump,
+    paths: [&Path; 2],
+    sysroot: &Path,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let first = std::fs::read_to_string(paths[0])?;
+    let second = std::fs::read_to_string(paths[1])?;
+    let first = parse_file(&first)?;
+    let second = parse_file(&second)?;
+
+    let (range, other_range) = match diff_ranges(goal, fmt, &first, &second)? {
+        Some([range, other_range]) => (Some(range), Some(other_range)),
+        None => (None, None),
+    };
+    let first = render_plain(sysroot, &first, fmt, range)?;
+    let second = render_plain(sysroot, &second, fmt, other_range)?;
+    let first = first.lines().collect::<Vec<_>>();
+    let second = second.lines().collect::<Vec<_>>();
+    let changes = diff::diff_lines(&first, &second);
+
+    let mut writer = std::io::stdout();
+    let header = format!("--- {}\n+++ {}", paths[0].display(), paths[1].display());
+    writeln!(writer, "{}", color!(header, OwoColorize::bold))?;
+    if !diff::write_unified(&mut writer, &changes, 3)? {
+        safeprintln!("No differences");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_range_to_json() {
+    use bpaf::Parser;
+    let fmt = crate::opts::format()
+        .to_options()
+        .run_inner(&["--json"])
+        .unwrap();
+    let asm = "\
+\t.file\t1 \"/tmp/foo/src/lib.rs\"
+_ZN3foo3bar17h0123456789abcdefE:
+\t.loc\t1 3 5 prologue_end
+\ttest\tedi, edi
+\tje\t.LBB0_2
+.LBB0_1:
+\tret
+.LBB0_2:
+\tjmp\t_ZN3foo3baz17h0123456789abcdefE
+";
+    let stmts = parse_file(asm).unwrap();
+    let mut files = BTreeMap::new();
+    let source = "pub fn bar(x: u32) {\n    // comment\n    if x != 0 {\n";
+    files.insert(
+        1,
+        (
+            Cow::Borrowed(Path::new("/tmp/foo/src/lib.rs")),
+            Some(CachedLines::without_ending(source.to_owned())),
+        ),
+    );
+    let dump_ctx = AsmDumpCtx {
+        files: &files,
+        fmt: &fmt,
+        stmts: &stmts,
+        inlined: None,
+        constants: None,
+        dyn_calls: None,
+    };
+    let source = json!({
+        "file": "/tmp/foo/src/lib.rs",
+        "line": 3,
+        "column": 5,
+        "rust": "if x != 0 {",
+    });
+    assert_eq!(
+        dump_ctx.range_to_json(Some(1..stmts.len())).unwrap(),
+        json!({ "statements": [
+            { "kind": "label", "id": "_ZN3foo3bar17h0123456789abcdefE", "name": "foo::bar" },
+            { "kind": "instruction", "op": "test", "args": "edi, edi", "source": source },
+            { "kind": "instruction", "op": "je", "args": ".LBB0_2", "source": source },
+            { "kind": "instruction", "op": "ret", "args": null, "source": source },
+            { "kind": "label", "id": ".LBB0_2", "name": ".LBB0_2" },
+            { "kind": "instruction", "op": "jmp", "args": "foo::baz", "source": source },
+        ]})
+    );
+
+    let Statement::Directive(Directive::Loc(loc)) = &stmts[2] else {
+        panic!("{:?} is not a .loc", stmts[2]);
+    };
+    // file without sources
+    assert_eq!(
+        loc_to_json(&BTreeMap::new(), loc),
+        json!({ "file": null, "line": 3, "column": 5, "rust": null })
+    );
+}
+
+#[test]
+fn test_diff_ranges() {
+    let first = "\
+\t.file\t1 \"/home/user/sample\" \"src/lib.rs\"
+\t.section\t.text.foo,\"ax\",@progbits
+foo:
+\t.loc\t1 10 0
+\tmov eax, 1
+\tret
+.Lfunc_end0:
+\t.section\t.text.bar,\"ax\",@progbits
+bar:
+\t.loc\t1 12 0
+\tmov eax, 2
+\tret
+.Lfunc_end1:
+";
+    let second = "\
+\t.file\t1 \"/home/user/sample\" \"src/lib.rs\"
+\t.section\t.text.bar,\"ax\",@progbits
+bar:
+\t.loc\t1 12 0
+\tmov eax, 3
+\tret
+.Lfunc_end0:
+";
+    let first = parse_file(first).unwrap();
+    let second = parse_file(second).unwrap();
+    let ranges = |args: &[&str]| {
+        let opts = crate::opts::options().run_inner(args).unwrap();
+        diff_ranges(opts.to_dump, &opts.format, &first, &second).unwrap()
+    };
+    let bar = Some([7..12, 1..6]);
+    assert_eq!(ranges(&["bar"]), bar);
+    // same function picked by source location
+    assert_eq!(ranges(&["src/lib.rs:12"]), bar);
+    assert_eq!(ranges(&["--everything"]), None);
+}
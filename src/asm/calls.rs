@@ -0,0 +1,249 @@
+//! Calls between functions
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::cfg::{flow, Flow};
+use super::statements::{Instruction, Statement};
+use crate::{demangle, Item};
+
+// symbol name, not preceded by something that makes it a part of a different word,
+// optionally followed by relocation kind: `@PLT`, `@GOTPCREL`, etc.
+static SYMBOL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:^|[^\w$.])([A-Za-z_$][\w$.]*)(@[A-Za-z]+)?").expect("regexp should be valid")
+});
+
+static HASH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"::h[0-9a-f]{16}$").expect("regexp should be valid"));
+
+/// Instruction calls a function or jumps to it as a tail call
+#[must_use]
+pub fn is_call_like(instr: &Instruction) -> bool {
+    matches!(
+        instr.op,
+        "call" | "callq" | "calll" | "bl" | "blr" | "jal" | "jalr"
+    ) || flow(instr) == Some(Flow::Jump)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Callee {
+    /// demangled name with hash, same as [`Item::hashed`] for functions defined in this file
+    pub hashed: String,
+    /// demangled name
+    pub name: String,
+}
+
+impl Callee {
+    fn new(hashed: String) -> Self {
+        let name = HASH.replace(&hashed, "").into_owned();
+        Self { hashed, name }
+    }
+}
+
+/// Functions called by the instruction, `known` are names of functions defined in this file
+///
+/// Indirect calls via registers are ignored, non rust symbols are only picked if they are
+/// defined in this file or come with a relocation kind, such as `memcpy@PLT`
+#[must_use]
+pub fn call_targets(instr: &Instruction, known: &BTreeSet<&str>) -> Vec<Callee> {
+    let Some(args) = instr.args.filter(|_| is_call_like(instr)) else {
+        return Vec::new();
+    };
+    SYMBOL
+        .captures_iter(args)
+        .filter_map(|cap| {
+            let symbol = cap.get(1)?.as_str();
+            if let Some(dem) = demangle::demangled(symbol) {
+                Some(Callee::new(format!("{dem:?}")))
+            } else if cap.get(2).is_some() || known.contains(symbol) {
+                Some(Callee::new(symbol.to_owned()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Unique functions called from statements, in order of the first call
+#[must_use]
+pub fn callees(stmts: &[Statement], known: &BTreeSet<&str>) -> Vec<Callee> {
+    let mut seen = BTreeSet::new();
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Instruction(instr) if stmt.is_instruction() => Some(instr),
+            _ => None,
+        })
+        .flat_map(|instr| call_targets(instr, known))
+        .filter(|callee| seen.insert(callee.hashed.clone()))
+        .collect()
+}
+
+/// Callees for every function in the file
+#[must_use]
+pub fn call_graph<'a>(
+    stmts: &[Statement],
+    items: &'a BTreeMap<Item, Range<usize>>,
+) -> Vec<(&'a Item, Vec<Callee>)> {
+    let known = items
+        .keys()
+        .map(|i| i.hashed.as_str())
+        .collect::<BTreeSet<_>>();
+    items
+        .iter()
+        .map(|(item, range)| (item, callees(&stmts[range.clone()], &known)))
+        .collect()
+}
+
+/// Functions defined in this file reachable from `root` via at most `depth` calls
+///
+/// Functions are listed in breadth first order, `root` itself is not included
+#[must_use]
+pub fn local_callees<'a>(
+    root: &Item,
+    stmts: &[Statement],
+    items: &'a BTreeMap<Item, Range<usize>>,
+    depth: usize,
+) -> Vec<(&'a Item, Range<usize>)> {
+    let known = items
+        .keys()
+        .map(|i| i.hashed.as_str())
+        .collect::<BTreeSet<_>>();
+    let by_name = items
+        .iter()
+        .map(|(item, range)| (item.hashed.as_str(), (item, range.clone())))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut seen = BTreeSet::from([root.hashed.as_str()]);
+    let mut res = Vec::new();
+    let mut level = by_name
+        .get(root.hashed.as_str())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for (_, range) in &level {
+            for callee in callees(&stmts[range.clone()], &known) {
+                if let Some((&name, item)) = by_name.get_key_value(callee.hashed.as_str()) {
+                    if seen.insert(name) {
+                        next.push(item.clone());
+                    }
+                }
+            }
+        }
+        if next.is_empty() {
+            break;
+        }
+        res.extend(next.iter().cloned());
+        level = next;
+    }
+    res
+}
+
+/// Write call graph in Graphviz DOT format, functions not defined in this file are dashed
+pub fn write_dot(
+    writer: &mut impl Write,
+    graph: &[(&Item, Vec<Callee>)],
+    full_name: bool,
+) -> std::io::Result<()> {
+    let defined = graph
+        .iter()
+        .map(|(item, _)| item.hashed.as_str())
+        .collect::<BTreeSet<_>>();
+    writeln!(writer, "digraph calls {{")?;
+    writeln!(writer, "    node [shape=box, fontname=\"monospace\"];")?;
+    for (item, _) in graph {
+        let label = if full_name { &item.hashed } else { &item.name };
+        writeln!(writer, "    {:?} [label={label:?}];", item.hashed)?;
+    }
+    let mut external = BTreeSet::new();
+    for (_, callees) in graph {
+        for callee in callees {
+            if !defined.contains(callee.hashed.as_str()) && external.insert(&callee.hashed) {
+                let label = if full_name {
+                    &callee.hashed
+                } else {
+                    &callee.name
+                };
+                writeln!(
+                    writer,
+                    "    {:?} [label={label:?}, style=dashed];",
+                    callee.hashed
+                )?;
+            }
+        }
+    }
+    for (item, callees) in graph {
+        for callee in callees {
+            writeln!(writer, "    {:?} -> {:?};", item.hashed, callee.hashed)?;
+        }
+    }
+    writeln!(writer, "}}")
+}
+
+#[test]
+fn test_local_callees() {
+    use super::{find_items, parse_file};
+    let asm = "\
+\t.section\t.text.foo,\"ax\",@progbits
+foo:
+\tcall\tbar
+\tcall\tbar
+\tret
+.Lfunc_end0:
+\t.section\t.text.bar,\"ax\",@progbits
+bar:
+\tjmp\tbaz
+.Lfunc_end1:
+\t.section\t.text.baz,\"ax\",@progbits
+baz:
+\tcall\tfoo
+\tret
+.Lfunc_end2:
+";
+    let stmts = parse_file(asm).unwrap();
+    let items = find_items(&stmts);
+    let foo = items.keys().find(|i| i.name == "foo").unwrap();
+    let names = |depth| {
+        local_callees(foo, &stmts, &items, depth)
+            .into_iter()
+            .map(|(i, _)| i.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert!(names(0).is_empty());
+    assert_eq!(names(1), ["bar"]);
+    assert_eq!(names(usize::MAX), ["bar", "baz"]);
+}
+
+#[test]
+fn test_call_targets() {
+    use super::parse_file;
+    let asm = "\
+\tcall\tqword ptr [rip + _ZN4core9panicking18panic_bounds_check17h0123456789abcdefE@GOTPCREL]
+\tcall\tmemcpy@PLT
+\tcall\trax
+\tjmp\tlocal_helper
+\tjne\t.LBB0_2
+\tbl\t_ZN6sample4main17hb59e25bba3071c26E
+\tlea\trdi, [rip + _ZN6sample4main17hb59e25bba3071c26E]
+";
+    let stmts = parse_file(asm).unwrap();
+    let known = BTreeSet::from(["local_helper"]);
+    let names = callees(&stmts, &known)
+        .into_iter()
+        .map(|c| c.name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "core::panicking::panic_bounds_check",
+            "memcpy",
+            "local_helper",
+            "sample::main"
+        ]
+    );
+}
@@ -0,0 +1,307 @@
+//! Control flow graph of a function: basic blocks connected by jumps and fallthroughs
+use std::{collections::BTreeMap, io::Write, ops::Range};
+
+use super::statements::{Instruction, Label, Statement};
+use crate::demangle::{self, LabelKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Unconditional jump, direct or indirect
+    Jump,
+    /// Conditional jump, falls through to the next instruction when not taken
+    Branch,
+    /// Return or a trap, control doesn't go anywhere else in this function
+    Exit,
+}
+
+/// How an instruction changes the control flow
+///
+/// `None` for instructions that continue to the next one, calls included
+#[must_use]
+pub fn flow(instr: &Instruction) -> Option<Flow> {
+    match instr.op {
+        // x86, AT&T syntax adds size suffixes
+        "jmp" | "jmpq" | "jmpl" => Some(Flow::Jump),
+        "ja" | "jae" | "jb" | "jbe" | "jc" | "je" | "jg" | "jge" | "jl" | "jle" | "jna"
+        | "jnae" | "jnb" | "jnbe" | "jnc" | "jne" | "jng" | "jnge" | "jnl" | "jnle" | "jno"
+        | "jnp" | "jns" | "jnz" | "jo" | "jp" | "jpe" | "jpo" | "js" | "jz" | "jcxz" | "jecxz"
+        | "jrcxz" | "loop" | "loope" | "loopne" | "loopz" | "loopnz" => Some(Flow::Branch),
+        "ret" | "retq" | "retl" | "ud2" | "hlt" | "int3" => Some(Flow::Exit),
+        // aarch64
+        "b" | "br" => Some(Flow::Jump),
+        "cbz" | "cbnz" | "tbz" | "tbnz" => Some(Flow::Branch),
+        "brk" | "udf" => Some(Flow::Exit),
+        // riscv, `jal` and `jalr` are calls
+        "j" | "jr" => Some(Flow::Jump),
+        "jal" | "jalr" => None,
+        "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" | "beqz" | "bnez" | "bltz" | "bgez"
+        | "blez" | "bgtz" => Some(Flow::Branch),
+        "unimp" => Some(Flow::Exit),
+        // wasm control flow is structured and doesn't use labels, only exits are interesting
+        "unreachable" | "return" | "end_function" => Some(Flow::Exit),
+        op if op.starts_with("b.") => Some(Flow::Branch),
+        _ => None,
+    }
+}
+
+/// Local label instruction jumps to, if any
+#[must_use]
+pub fn jump_target<'a>(instr: &Instruction<'a>) -> Option<&'a str> {
+    demangle::local_label_names(instr.args?).next()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Unconditional jump
+    Jump,
+    /// Conditional jump, taken
+    Taken,
+    /// Conditional jump not taken or a block that ends without a jump
+    Fallthrough,
+}
+
+impl EdgeKind {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeKind::Jump => "jump",
+            EdgeKind::Taken => "taken",
+            EdgeKind::Fallthrough => "fallthrough",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block<'a> {
+    /// Labels pointing to the start of this block
+    pub labels: Vec<&'a str>,
+    /// Statements of this block, including directives
+    pub range: Range<usize>,
+    /// Last instruction in the block changes the control flow
+    exit: Option<(Flow, Option<&'a str>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cfg<'a> {
+    pub blocks: Vec<Block<'a>>,
+    pub edges: Vec<Edge>,
+}
+
+impl<'a> Cfg<'a> {
+    /// Split statements of a single function into basic blocks
+    ///
+    /// Blocks start at labels something jumps to and after instructions that change
+    /// the control flow
+    #[must_use]
+    pub fn new(stmts: &[Statement<'a>]) -> Self {
+        let used = super::used_label_names(stmts);
+        let mut blocks = Vec::new();
+        let mut current = Block {
+            labels: Vec::new(),
+            range: 0..0,
+            exit: None,
+        };
+        let mut has_instructions = false;
+
+        for (ix, stmt) in stmts.iter().enumerate() {
+            match stmt {
+                Statement::Label(Label {
+                    id,
+                    kind: LabelKind::Local,
+                }) if used.contains(id) => {
+                    if has_instructions {
+                        current.range.end = ix;
+                        blocks.push(std::mem::replace(
+                            &mut current,
+                            Block {
+                                labels: Vec::new(),
+                                range: ix..ix,
+                                exit: None,
+                            },
+                        ));
+                        has_instructions = false;
+                    }
+                    current.labels.push(id);
+                }
+                Statement::Instruction(instr) if stmt.is_instruction() => {
+                    has_instructions = true;
+                    // panicking functions never return
+                    let panics = || {
+                        instr.args.is_some_and(|args| {
+                            demangle::symbols(args).any(|name| super::panics::is_panicking(&name))
+                        })
+                    };
+                    if let Some(flow) = flow(instr).or_else(|| panics().then_some(Flow::Exit)) {
+                        current.range.end = ix + 1;
+                        current.exit = Some((flow, jump_target(instr)));
+                        blocks.push(std::mem::replace(
+                            &mut current,
+                            Block {
+                                labels: Vec::new(),
+                                range: ix + 1..ix + 1,
+                                exit: None,
+                            },
+                        ));
+                        has_instructions = false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if has_instructions || !current.labels.is_empty() {
+            current.range.end = stmts.len();
+            blocks.push(current);
+        }
+
+        let by_label = blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(ix, block)| block.labels.iter().map(move |l| (*l, ix)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut edges = Vec::new();
+        for (from, block) in blocks.iter().enumerate() {
+            let next = (from + 1 < blocks.len()).then_some(from + 1);
+            let target = |label: Option<&str>| by_label.get(label?).copied();
+            let mut edge = |to: Option<usize>, kind| {
+                if let Some(to) = to {
+                    edges.push(Edge { from, to, kind });
+                }
+            };
+            match block.exit {
+                None => edge(next, EdgeKind::Fallthrough),
+                Some((Flow::Jump, label)) => edge(target(label), EdgeKind::Jump),
+                Some((Flow::Branch, label)) => {
+                    edge(target(label), EdgeKind::Taken);
+                    edge(next, EdgeKind::Fallthrough);
+                }
+                Some((Flow::Exit, _)) => {}
+            }
+        }
+
+        Self { blocks, edges }
+    }
+
+    /// Write the graph in Graphviz DOT format, `title` is used for the entry block without labels
+    pub fn write_dot(
+        &self,
+        writer: &mut impl Write,
+        title: &str,
+        stmts: &[Statement],
+        full_name: bool,
+    ) -> std::io::Result<()> {
+        writeln!(writer, "digraph {:?} {{", title)?;
+        writeln!(writer, "    node [shape=box, fontname=\"monospace\"];")?;
+        for (ix, block) in self.blocks.iter().enumerate() {
+            let mut label = if block.labels.is_empty() && ix == 0 {
+                format!("{title}:\\l")
+            } else {
+                block.labels.iter().map(|l| format!("{l}:\\l")).collect()
+            };
+            for stmt in &stmts[block.range.clone()] {
+                if let Statement::Instruction(instr) = stmt {
+                    if stmt.is_instruction() {
+                        let text = if full_name {
+                            format!("{instr:#}")
+                        } else {
+                            format!("{instr}")
+                        };
+                        label.push_str("    ");
+                        label.push_str(&escape(&text));
+                        label.push_str("\\l");
+                    }
+                }
+            }
+            writeln!(writer, "    b{ix} [label=\"{label}\"];")?;
+        }
+        for edge in &self.edges {
+            let color = match edge.kind {
+                EdgeKind::Jump => "blue",
+                EdgeKind::Taken => "darkgreen",
+                EdgeKind::Fallthrough => "red",
+            };
+            writeln!(
+                writer,
+                "    b{} -> b{} [color={color}, tooltip={:?}];",
+                edge.from,
+                edge.to,
+                edge.kind.name()
+            )?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+fn escape(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{flow, Cfg, Edge, EdgeKind, Flow, Instruction};
+    use crate::asm::parse_file;
+
+    #[test]
+    fn blocks_and_edges() {
+        let asm = "\
+foo:
+\ttest edi, edi
+\tje .LBB0_2
+\tmov eax, 1
+.LBB0_1:
+\tadd eax, 1
+\tcmp eax, 10
+\tjne .LBB0_1
+\tret
+.LBB0_2:
+\txor eax, eax
+\tret
+";
+        let stmts = parse_file(asm).unwrap();
+        let cfg = Cfg::new(&stmts);
+        let labels = cfg
+            .blocks
+            .iter()
+            .map(|b| b.labels.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [vec![], vec![], vec![".LBB0_1"], vec![], vec![".LBB0_2"]]
+        );
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            cfg.edges,
+            [
+                edge(0, 4, EdgeKind::Taken),
+                edge(0, 1, EdgeKind::Fallthrough),
+                edge(1, 2, EdgeKind::Fallthrough),
+                edge(2, 2, EdgeKind::Taken),
+                edge(2, 3, EdgeKind::Fallthrough),
+            ]
+        );
+    }
+
+    #[test]
+    fn flow_by_mnemonic() {
+        let flow_of = |op| flow(&Instruction { op, args: None });
+        assert_eq!(flow_of("jne"), Some(Flow::Branch));
+        assert_eq!(flow_of("jrcxz"), Some(Flow::Branch));
+        assert_eq!(flow_of("jmpq"), Some(Flow::Jump));
+        assert_eq!(flow_of("b.ne"), Some(Flow::Branch));
+        assert_eq!(flow_of("cbz"), Some(Flow::Branch));
+        assert_eq!(flow_of("retq"), Some(Flow::Exit));
+        // not jumps despite the first letter
+        assert_eq!(flow_of("jal"), None);
+        assert_eq!(flow_of("jsr"), None);
+    }
+}
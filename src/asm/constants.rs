@@ -0,0 +1,318 @@
+//! Values of constant pool entries and string literals referenced by instructions
+use std::collections::BTreeMap;
+
+use super::statements::{parse_quoted_string, Directive, GenericDirective, Instruction, Statement};
+use crate::demangle;
+
+/// Show at most this many elements of an array
+const MAX_ELEMENTS: usize = 16;
+
+/// Show at most this many characters of a string
+const MAX_CHARS: usize = 80;
+
+/// Data directives following local labels, by label
+#[derive(Debug, Default)]
+pub struct Constants<'a> {
+    /// directive name and arguments: `.long 1, 2` is stored as `("long", "1, 2")`
+    data: BTreeMap<&'a str, Vec<(&'a str, &'a str)>>,
+}
+
+fn data_directive<'a>(stmt: &Statement<'a>) -> Option<(&'a str, &'a str)> {
+    let Statement::Directive(Directive::Generic(GenericDirective(dir))) = stmt else {
+        return None;
+    };
+    let (name, args) = dir.split_once(char::is_whitespace)?;
+    matches!(
+        name,
+        "byte"
+            | "short"
+            | "value"
+            | "2byte"
+            | "hword"
+            | "long"
+            | "int"
+            | "4byte"
+            | "word"
+            | "quad"
+            | "8byte"
+            | "xword"
+            | "ascii"
+            | "asciz"
+            | "string"
+            | "zero"
+            | "space"
+    )
+    .then_some((name, args.trim()))
+}
+
+impl<'a> Constants<'a> {
+    /// Collect data for every local label in the file
+    ///
+    /// Labels immediately followed by other labels share the data, alignment and other directives
+    /// between the label and the data are skipped
+    #[must_use]
+    pub fn new(stmts: &[Statement<'a>]) -> Self {
+        let mut data = BTreeMap::new();
+        let mut labels = Vec::new();
+        let mut current = Vec::new();
+        let mut flush = |labels: &mut Vec<&'a str>, current: &mut Vec<(&'a str, &'a str)>| {
+            if !current.is_empty() {
+                for label in labels.drain(..) {
+                    data.insert(label, current.clone());
+                }
+                current.clear();
+            }
+        };
+        for stmt in stmts {
+            if let Some(dir) = data_directive(stmt).filter(|_| !labels.is_empty()) {
+                current.push(dir);
+                continue;
+            }
+            match stmt {
+                Statement::Label(label) => {
+                    flush(&mut labels, &mut current);
+                    if demangle::local_label_names(label.id).next() == Some(label.id) {
+                        labels.push(label.id);
+                    }
+                }
+                Statement::Directive(_) | Statement::Nothing => {
+                    if !current.is_empty() {
+                        flush(&mut labels, &mut current);
+                        labels.clear();
+                    }
+                }
+                Statement::Instruction(_) | Statement::Dunno(_) => {
+                    flush(&mut labels, &mut current);
+                    labels.clear();
+                }
+            }
+        }
+        flush(&mut labels, &mut current);
+        Self { data }
+    }
+
+    /// Labels and data directives following them
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &[(&'a str, &'a str)])> {
+        self.data
+            .iter()
+            .map(|(label, data)| (*label, data.as_slice()))
+    }
+
+    /// Decoded value of the first constant the instruction refers to
+    #[must_use]
+    pub fn describe(&self, instr: &Instruction, full_name: bool) -> Option<String> {
+        let dirs = demangle::local_label_names(instr.args?).find_map(|l| self.data.get(l))?;
+        let mut elements = Vec::new();
+        for (name, args) in dirs {
+            decode(name, args, full_name, &mut elements);
+        }
+        Some(match elements.as_slice() {
+            [single] => single.clone(),
+            _ if elements.len() > MAX_ELEMENTS => {
+                format!("[{}, …]", elements[..MAX_ELEMENTS].join(", "))
+            }
+            _ => format!("[{}]", elements.join(", ")),
+        })
+    }
+}
+
+/// Render values of a single data directive
+///
+/// LLVM prints floating point constants as hex numbers and integers as decimal ones,
+/// hex `.long` and `.quad` values are also shown as `f32` and `f64` if that gives a reasonable
+/// number, tables of integers and pointers can be in hex too
+fn decode(name: &str, args: &str, full_name: bool, elements: &mut Vec<String>) {
+    match name {
+        "ascii" | "asciz" | "string" => {
+            let Ok((_, string)) = parse_quoted_string(args) else {
+                elements.push(args.to_owned());
+                return;
+            };
+            // octal escapes produce chars in 0..=255 range, one per byte
+            #[allow(clippy::cast_possible_truncation)]
+            let mut bytes = string.chars().map(|c| c as u8).collect::<Vec<_>>();
+            // `.asciz` and `.string` end with a zero byte the quoted string doesn't include
+            let terminated = name != "ascii";
+            if terminated {
+                bytes.push(0);
+            }
+            elements.push(render_bytes(&bytes, terminated));
+        }
+        "zero" | "space" => {
+            let (len, fill) = args.split_once(',').unwrap_or((args, "0"));
+            elements.push(format!("[{}; {}]", fill.trim(), len.trim()));
+        }
+        _ => {
+            for arg in args.split(',').map(str::trim) {
+                let hex = arg
+                    .strip_prefix("0x")
+                    .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+                let float = match (name, hex) {
+                    ("long" | "int" | "4byte" | "word", Some(bits)) => u32::try_from(bits)
+                        .ok()
+                        .map(f32::from_bits)
+                        .filter(|f| plausible(f64::from(*f)))
+                        .map(|f| format!("{f:?}")),
+                    ("quad" | "8byte" | "xword", Some(bits)) => Some(f64::from_bits(bits))
+                        .filter(|f| plausible(*f))
+                        .map(|f| format!("{f:?}")),
+                    _ => None,
+                };
+                let value = demangle::contents(arg, full_name);
+                elements.push(match float {
+                    Some(float) => format!("{value} ({float})"),
+                    None => value.into_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Floating point number that looks like a constant someone would write rather than
+/// an integer or a pointer reinterpreted as one
+fn plausible(value: f64) -> bool {
+    value.is_normal() && (1e-9..1e15).contains(&value.abs())
+}
+
+/// Text as a string literal, anything else as a byte string literal
+///
+/// Zero byte at the end of `terminated` strings is only shown in byte strings
+fn render_bytes(bytes: &[u8], terminated: bool) -> String {
+    let text = match bytes.split_last() {
+        Some((0, text)) if terminated => text,
+        _ => bytes,
+    };
+    match std::str::from_utf8(text) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+            let mut res = text.chars().take(MAX_CHARS).collect::<String>();
+            if res.len() < text.len() {
+                res.push('…');
+            }
+            format!("{res:?}")
+        }
+        _ => {
+            let escaped = bytes
+                .iter()
+                .take(MAX_CHARS)
+                .flat_map(|b| std::ascii::escape_default(*b))
+                .map(char::from)
+                .collect::<String>();
+            let more = if bytes.len() > MAX_CHARS { "…" } else { "" };
+            format!("b\"{escaped}{more}\"")
+        }
+    }
+}
+
+#[test]
+fn test_describe() {
+    use super::parse_file;
+    let asm = "\
+\t.section\t.rodata.cst16,\"aM\",@progbits,16
+\t.p2align\t4, 0x0
+.LCPI1_0:
+\t.long\t0x3f800000
+\t.long\t0x40000000
+\t.long\t0x40600000
+\t.long\t0xc0800000
+.LCPI1_1:
+\t.quad\t0x3ff8000000000000
+.LCPI1_3:
+\t.long\t0x80000000
+\t.long\t0xffffffff
+\t.long\t0x7f
+\t.quad\t0x55d4a80f10c0
+.LCPI1_2:
+\t.long\t236
+\t.long\t242
+\t.zero\t8,128
+\t.section\t.text.g,\"ax\",@progbits
+g:
+\tmulps\txmm0, xmmword ptr [rip + .LCPI1_0]
+\tmulsd\txmm0, qword ptr [rip + .LCPI1_1]
+\tmovdqa\txmm1, xmmword ptr [rip + .LCPI1_3]
+\tpaddd\txmm0, xmmword ptr [rip + .LCPI1_2]
+\tlea\trax, [rip + .Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.0]
+\tlea\trax, [rip + .Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.1]
+\tjmp\t.LBB0_1
+\tret
+\t.section\t.rodata..Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.0,\"a\",@progbits
+.Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.0:
+\t.ascii\t\"hello world\\n\"
+\t.size\t.Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.0, 12
+
+.Lanon.ad3321bbfbd6af8abcf0f0576a5c893f.1:
+\t.asciz\t\"\\300\\001\\n\"
+";
+    let stmts = parse_file(asm).unwrap();
+    let constants = Constants::new(&stmts);
+    let described = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Instruction(instr) => constants.describe(instr, false),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        described,
+        [
+            "[0x3f800000 (1.0), 0x40000000 (2.0), 0x40600000 (3.5), 0xc0800000 (-4.0)]",
+            "0x3ff8000000000000 (1.5)",
+            "[0x80000000, 0xffffffff, 0x7f, 0x55d4a80f10c0]",
+            "[236, 242, [128; 8]]",
+            "\"hello world\\n\"",
+            "b\"\\xc0\\x01\\n\\x00\"",
+        ]
+    );
+}
+
+#[test]
+fn test_labels_and_data() {
+    use super::parse_file;
+    let asm = "\
+.LCPI0_0:
+.LCPI0_1:
+\t.p2align\t3, 0x0
+\t.quad\t1
+\t.quad\t2
+\t.p2align\t3, 0x0
+.LCPI0_2:
+\tret
+\t.quad\t3
+\t.section\t.rodata.cst8,\"aM\",@progbits,8
+.LCPI1_0:
+\t.quad\t4
+";
+    let stmts = parse_file(asm).unwrap();
+    let constants = Constants::new(&stmts);
+    let data = constants
+        .iter()
+        .map(|(label, data)| (label, data.iter().map(|d| d.1).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        data,
+        [
+            (".LCPI0_0", vec!["1", "2"]),
+            (".LCPI0_1", vec!["1", "2"]),
+            (".LCPI1_0", vec!["4"]),
+        ]
+    );
+}
+
+#[test]
+fn test_render_strings() {
+    let render = |name, args| {
+        let mut elements = Vec::new();
+        decode(name, args, false, &mut elements);
+        elements.join(", ")
+    };
+    assert_eq!(render("asciz", r#""hello""#), r#""hello""#);
+    assert_eq!(render("string", r#""hello""#), r#""hello""#);
+    // `[1u32, 2, 3]`, the last zero byte is the terminator
+    assert_eq!(
+        render("asciz", r#""\001\000\000\000\002\000\000\000\003\000\000""#),
+        r#"b"\x01\x00\x00\x00\x02\x00\x00\x00\x03\x00\x00\x00""#
+    );
+    // zero byte before the terminator is data
+    assert_eq!(render("asciz", r#""hi\000""#), r#"b"hi\x00\x00""#);
+    assert_eq!(render("ascii", r#""hi\000""#), r#"b"hi\x00""#);
+}
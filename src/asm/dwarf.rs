@@ -0,0 +1,738 @@
+//! Inlining information recovered from DWARF debug info emitted along with the code
+//!
+//! Debug sections come as data directives such as `.byte 1` or `.long .Linfo_string3`,
+//! values are either numbers or expressions with labels so sections are kept as a list
+//! of such values rather than as bytes. Only DWARF 4 used by rustc on Linux is supported,
+//! for anything else there's simply no inlining information.
+use std::{borrow::Cow, collections::BTreeMap, ops::Range};
+
+use super::statements::{parse_quoted_string, Directive, GenericDirective, Label, Statement};
+use crate::demangle;
+
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_RANGES: u64 = 0x55;
+const DW_AT_CALL_LINE: u64 = 0x59;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+
+/// A single value from a data directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value<'a> {
+    Bytes(Vec<u8>),
+    Expr { size: usize, expr: &'a str },
+}
+
+/// Attribute value as read from `.debug_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Attr<'a> {
+    Num(u64),
+    Expr(&'a str),
+    Str(String),
+}
+
+impl Value<'_> {
+    fn parse(dir: &str) -> Option<Value<'_>> {
+        let (op, arg) = dir.split_once(|c: char| c.is_ascii_whitespace())?;
+        let arg = arg.trim();
+        let size = match op {
+            "byte" => 1,
+            "short" | "hword" | "2byte" => 2,
+            "long" | "word" | "4byte" => 4,
+            "quad" | "xword" | "8byte" => 8,
+            "ascii" | "asciz" => {
+                let (_, s) = parse_quoted_string(arg).ok()?;
+                let mut bytes = Vec::with_capacity(s.len() + 1);
+                for c in s.chars() {
+                    // octal escapes are parsed into chars, they are really bytes
+                    match u8::try_from(u32::from(c)) {
+                        Ok(b) => bytes.push(b),
+                        Err(_) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                if op == "asciz" {
+                    bytes.push(0);
+                }
+                return Some(Value::Bytes(bytes));
+            }
+            "uleb128" => {
+                let mut n = number(arg)?;
+                let mut bytes = Vec::new();
+                loop {
+                    let byte = (n & 0x7f) as u8;
+                    n >>= 7;
+                    if n == 0 {
+                        bytes.push(byte);
+                        return Some(Value::Bytes(bytes));
+                    }
+                    bytes.push(byte | 0x80);
+                }
+            }
+            _ => return None,
+        };
+        Some(match number(arg) {
+            Some(n) => Value::Bytes(n.to_le_bytes()[..size].to_vec()),
+            None => Value::Expr { size, expr: arg },
+        })
+    }
+}
+
+fn number(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(neg) = s.strip_prefix('-') {
+        neg.parse::<u64>().ok().map(u64::wrapping_neg)
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Sequential reader over section values
+struct Reader<'s, 'a> {
+    values: &'s [Value<'a>],
+    ix: usize,
+    /// offset inside of the current `Value::Bytes`
+    offset: usize,
+    /// offset from the start of the section
+    pos: usize,
+}
+
+impl<'s, 'a> Reader<'s, 'a> {
+    fn new(values: &'s [Value<'a>]) -> Self {
+        Self {
+            values,
+            ix: 0,
+            offset: 0,
+            pos: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ix >= self.values.len()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let Value::Bytes(bytes) = self.values.get(self.ix)? else {
+            return None;
+        };
+        let byte = *bytes.get(self.offset)?;
+        self.offset += 1;
+        self.pos += 1;
+        if self.offset == bytes.len() {
+            self.ix += 1;
+            self.offset = 0;
+        }
+        Some(byte)
+    }
+
+    fn fixed(&mut self, size: usize) -> Option<Attr<'a>> {
+        if let Some(&Value::Expr { size: s, expr }) = self.values.get(self.ix) {
+            if s != size || self.offset != 0 {
+                return None;
+            }
+            self.ix += 1;
+            self.pos += size;
+            return Some(Attr::Expr(expr));
+        }
+        let mut res = 0u64;
+        for i in 0..size {
+            res |= u64::from(self.byte()?) << (8 * i);
+        }
+        Some(Attr::Num(res))
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let mut res = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift < 64 {
+                res |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(res);
+            }
+        }
+    }
+
+    /// Skip `size` bytes, blocks can contain expressions such as an address in `DW_OP_addr`
+    fn skip(&mut self, mut size: usize) -> Option<()> {
+        while size > 0 {
+            match self.values.get(self.ix)? {
+                &Value::Expr { size: s, .. } if s <= size => {
+                    self.fixed(s)?;
+                    size -= s;
+                }
+                _ => {
+                    self.byte()?;
+                    size -= 1;
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn cstr(&mut self) -> Option<String> {
+        let mut bytes = Vec::new();
+        loop {
+            match self.byte()? {
+                0 => return Some(String::from_utf8_lossy(&bytes).into_owned()),
+                b => bytes.push(b),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Abbrev {
+    tag: u64,
+    children: bool,
+    attrs: Vec<(u64, u64)>,
+}
+
+fn parse_abbrevs(values: &[Value]) -> Option<BTreeMap<u64, Abbrev>> {
+    let mut reader = Reader::new(values);
+    let mut res = BTreeMap::new();
+    loop {
+        let code = reader.uleb()?;
+        if code == 0 {
+            return Some(res);
+        }
+        let tag = reader.uleb()?;
+        let children = reader.byte()? != 0;
+        let mut attrs = Vec::new();
+        loop {
+            let attr = reader.uleb()?;
+            let form = reader.uleb()?;
+            if attr == 0 && form == 0 {
+                break;
+            }
+            attrs.push((attr, form));
+        }
+        res.insert(
+            code,
+            Abbrev {
+                tag,
+                children,
+                attrs,
+            },
+        );
+    }
+}
+
+/// Read attribute value of a given form, values of uninteresting forms are skipped
+fn read_form<'a>(reader: &mut Reader<'_, 'a>, form: u64) -> Option<Option<Attr<'a>>> {
+    Some(match form {
+        // addr, data8, ref8, ref_sig8
+        0x01 | 0x07 | 0x14 | 0x20 => Some(reader.fixed(8)?),
+        // data1, flag, ref1
+        0x0b | 0x0c | 0x11 => Some(reader.fixed(1)?),
+        // data2, ref2
+        0x05 | 0x12 => Some(reader.fixed(2)?),
+        // data4, strp, ref_addr, ref4, sec_offset
+        0x06 | 0x0e | 0x10 | 0x13 | 0x17 => Some(reader.fixed(4)?),
+        // udata, ref_udata
+        0x0f | 0x15 => Some(Attr::Num(reader.uleb()?)),
+        // sdata, value is not interesting
+        0x0d => {
+            reader.uleb()?;
+            None
+        }
+        // string
+        0x08 => Some(Attr::Str(reader.cstr()?)),
+        // block1
+        0x0a => {
+            let len = reader.byte()?;
+            reader.skip(usize::from(len))?;
+            None
+        }
+        // block2, block4
+        0x03 | 0x04 => {
+            let Attr::Num(len) = reader.fixed(if form == 0x03 { 2 } else { 4 })? else {
+                return None;
+            };
+            reader.skip(usize::try_from(len).ok()?)?;
+            None
+        }
+        // block, exprloc
+        0x09 | 0x18 => {
+            let len = reader.uleb()?;
+            reader.skip(usize::try_from(len).ok()?)?;
+            None
+        }
+        // flag_present
+        0x19 => None,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+struct Die<'a> {
+    tag: u64,
+    parent: Option<usize>,
+    attrs: BTreeMap<u64, Attr<'a>>,
+}
+
+/// Parse all the DIEs from `.debug_info`, keys are offsets from the start of the section
+fn parse_dies<'a>(
+    values: &[Value<'a>],
+    abbrevs: &BTreeMap<u64, Abbrev>,
+) -> Option<BTreeMap<usize, Die<'a>>> {
+    let mut reader = Reader::new(values);
+    let mut res = BTreeMap::new();
+    while !reader.is_empty() {
+        let cu_start = reader.pos;
+        let _unit_length = reader.fixed(4)?;
+        let Attr::Num(4) = reader.fixed(2)? else {
+            return None;
+        };
+        let _abbrev_offset = reader.fixed(4)?;
+        let Attr::Num(8) = reader.fixed(1)? else {
+            return None;
+        };
+
+        let mut parents: Vec<usize> = Vec::new();
+        loop {
+            let offset = reader.pos;
+            let code = reader.uleb()?;
+            if code == 0 {
+                if parents.pop().is_none() || parents.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            let abbrev = abbrevs.get(&code)?;
+            let mut die = Die {
+                tag: abbrev.tag,
+                parent: parents.last().copied(),
+                attrs: BTreeMap::new(),
+            };
+            for &(attr, form) in &abbrev.attrs {
+                let Some(mut value) = read_form(&mut reader, form)? else {
+                    continue;
+                };
+                // references are relative to the unit
+                if let (0x11..=0x15, Attr::Num(n)) = (form, &mut value) {
+                    *n += cu_start as u64;
+                }
+                die.attrs.insert(attr, value);
+            }
+            res.insert(offset, die);
+            if abbrev.children {
+                parents.push(offset);
+            }
+        }
+    }
+    Some(res)
+}
+
+/// Function inlined into another one, possibly into another inlined function
+#[derive(Debug, Clone)]
+pub struct Inlined {
+    /// demangled name of the inlined function
+    pub name: String,
+    /// line in the caller
+    pub call_line: u64,
+    /// statements with the inlined code
+    ranges: Vec<Range<usize>>,
+    /// enclosing inlined function
+    parent: Option<usize>,
+    /// name of the function everything is inlined into
+    root: Option<usize>,
+}
+
+/// Inlined functions for the whole file
+#[derive(Debug, Clone, Default)]
+pub struct InlineInfo {
+    inlined: Vec<Inlined>,
+    /// names of functions everything is inlined into
+    roots: Vec<String>,
+}
+
+impl InlineInfo {
+    /// Recover inlining information from debug sections in `stmts`,
+    /// empty if debug info is missing or can't be understood
+    #[must_use]
+    pub fn new(stmts: &[Statement]) -> Self {
+        Self::parse(stmts).unwrap_or_default()
+    }
+
+    fn parse(stmts: &[Statement]) -> Option<Self> {
+        let mut sections: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+        let mut strings = BTreeMap::new();
+        let mut ranges: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        let mut labels = BTreeMap::new();
+
+        let mut section = None;
+        let mut last_label = None;
+        let mut pending_range = None;
+        for (ix, stmt) in stmts.iter().enumerate() {
+            match stmt {
+                Statement::Directive(Directive::SectionStart(s)) => {
+                    // ELF uses `.debug_info`, Mach-O uses `__DWARF,__debug_info`
+                    section = s.find("debug_").map(|start| {
+                        let name = &s[start..];
+                        let end = name
+                            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                            .unwrap_or(name.len());
+                        &name[..end]
+                    });
+                    last_label = None;
+                }
+                Statement::Directive(Directive::Generic(GenericDirective(dir)))
+                    if matches!(*dir, "text" | "data" | "bss") =>
+                {
+                    section = None;
+                }
+                Statement::Label(Label { id, .. }) => {
+                    labels.entry(*id).or_insert(ix);
+                    last_label = Some(*id);
+                }
+                Statement::Directive(Directive::Generic(GenericDirective(dir))) => {
+                    let Some(section) = section else { continue };
+                    let Some(value) = Value::parse(dir) else {
+                        continue;
+                    };
+                    match (section.trim_start_matches("debug_"), &value) {
+                        ("str", Value::Bytes(bytes)) => {
+                            if let Some(label) = last_label.take() {
+                                let s = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+                                strings.insert(label, String::from_utf8_lossy(s).into_owned());
+                            }
+                        }
+                        ("ranges", Value::Expr { expr, .. }) => {
+                            let label = last_label.unwrap_or_default();
+                            match pending_range.take() {
+                                None => pending_range = Some(*expr),
+                                Some(start) => {
+                                    ranges.entry(label).or_default().push((start, *expr))
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    sections.entry(section).or_default().push(value);
+                }
+                _ => {}
+            }
+        }
+
+        let abbrevs = parse_abbrevs(sections.get("debug_abbrev")?)?;
+        let dies = parse_dies(sections.get("debug_info")?, &abbrevs)?;
+
+        let string = |attr: &Attr| match attr {
+            Attr::Str(s) => Some(s.clone()),
+            Attr::Expr(label) => strings.get(label).cloned(),
+            Attr::Num(_) => None,
+        };
+        // name of a subprogram, following abstract origins and declarations
+        let name_of = |mut offset: usize| {
+            for _ in 0..4 {
+                let die = dies.get(&offset)?;
+                if let Some(name) = die.attrs.get(&DW_AT_LINKAGE_NAME).and_then(string) {
+                    return Some(match demangle::demangled(&name) {
+                        Some(dem) => format!("{dem:#}"),
+                        None => name,
+                    });
+                }
+                match die
+                    .attrs
+                    .get(&DW_AT_ABSTRACT_ORIGIN)
+                    .or_else(|| die.attrs.get(&DW_AT_SPECIFICATION))
+                {
+                    Some(Attr::Num(next)) => offset = usize::try_from(*next).ok()?,
+                    _ => return die.attrs.get(&DW_AT_NAME).and_then(string),
+                }
+            }
+            None
+        };
+        // label the expression starts with: `.Ltmp15-.Ltmp12` is `.Ltmp15`
+        let stmt_of = |expr: &str| labels.get(expr.split('-').next()?.trim()).copied();
+
+        let mut res = Self::default();
+        let mut by_offset = BTreeMap::new();
+        let mut roots = BTreeMap::new();
+        for (&offset, die) in &dies {
+            if die.tag == DW_TAG_SUBPROGRAM && die.attrs.contains_key(&DW_AT_LOW_PC) {
+                roots.insert(offset, res.roots.len());
+                res.roots.push(name_of(offset).unwrap_or_default());
+                continue;
+            }
+            if die.tag != DW_TAG_INLINED_SUBROUTINE {
+                continue;
+            }
+            let stmt_ranges = match (
+                die.attrs.get(&DW_AT_LOW_PC),
+                die.attrs.get(&DW_AT_HIGH_PC),
+                die.attrs.get(&DW_AT_RANGES),
+            ) {
+                (Some(Attr::Expr(low)), Some(Attr::Expr(high)), _) => {
+                    std::iter::once(stmt_of(low)?..stmt_of(high)?).collect()
+                }
+                (_, _, Some(Attr::Expr(label))) => ranges
+                    .get(label)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(start, end)| Some(stmt_of(start)?..stmt_of(end)?))
+                    .collect(),
+                _ => continue,
+            };
+
+            // closest inlined function or subprogram above this one, skipping lexical blocks
+            let mut parent = None;
+            let mut root = None;
+            let mut up = die.parent;
+            while let Some(p) = up {
+                if let Some(&ix) = by_offset.get(&p) {
+                    parent = Some(ix);
+                    root = res.inlined.get(ix).and_then(|i: &Inlined| i.root);
+                    break;
+                }
+                if let Some(&ix) = roots.get(&p) {
+                    root = Some(ix);
+                    break;
+                }
+                up = dies.get(&p).and_then(|d| d.parent);
+            }
+
+            let Some(name) = die.attrs.get(&DW_AT_ABSTRACT_ORIGIN).and_then(|o| match o {
+                Attr::Num(o) => name_of(usize::try_from(*o).ok()?),
+                _ => None,
+            }) else {
+                continue;
+            };
+            let call_line = match die.attrs.get(&DW_AT_CALL_LINE) {
+                Some(Attr::Num(n)) => *n,
+                _ => 0,
+            };
+            by_offset.insert(offset, res.inlined.len());
+            res.inlined.push(Inlined {
+                name,
+                call_line,
+                ranges: stmt_ranges,
+                parent,
+                root,
+            });
+        }
+        Some(res)
+    }
+
+    /// Is there any inlining information at all?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inlined.is_empty()
+    }
+
+    /// Chain of inlined functions the statement at `ix` belongs to, outermost first,
+    /// together with the name of the function everything is inlined into
+    #[must_use]
+    pub fn chain(&self, ix: usize) -> Option<(&str, Vec<&Inlined>)> {
+        // innermost function is the one with the deepest nesting
+        let innermost = self
+            .inlined
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.ranges.iter().any(|r| r.contains(&ix)))
+            .max_by_key(|(ix, _)| self.depth(*ix))?
+            .0;
+        let mut chain = Vec::new();
+        let mut cur = Some(innermost);
+        while let Some(ix) = cur {
+            let inlined = &self.inlined[ix];
+            chain.push(inlined);
+            cur = inlined.parent;
+        }
+        chain.reverse();
+        let root = chain[0].root.map_or("", |r| self.roots[r].as_str());
+        Some((root, chain))
+    }
+
+    fn depth(&self, mut ix: usize) -> usize {
+        let mut depth = 0;
+        while let Some(parent) = self.inlined[ix].parent {
+            depth += 1;
+            ix = parent;
+        }
+        depth
+    }
+}
+
+/// Render an inlining chain as `my_fn:42 > Iterator::next:130 > slice::iter::next:150`,
+/// `line` is the line in the innermost function
+#[must_use]
+pub fn breadcrumb(root: &str, chain: &[&Inlined], line: u64, full_name: bool) -> String {
+    let name = |name: &str| -> String {
+        if full_name {
+            name.to_owned()
+        } else {
+            shorten(name).into_owned()
+        }
+    };
+    let mut res = name(root);
+    for inlined in chain {
+        res.push_str(&format!(":{} > {}", inlined.call_line, name(&inlined.name)));
+    }
+    res.push_str(&format!(":{line}"));
+    res
+}
+
+/// Drop module paths from a name, keeping types, traits and the function name
+fn shorten(name: &str) -> Cow<'_, str> {
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+    static MODULES: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\b[a-z_][a-z0-9_]*::").expect("regexp should be valid"));
+    MODULES.replace_all(name, "")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{breadcrumb, shorten, InlineInfo};
+    use crate::asm::parse_file;
+
+    #[test]
+    fn short_names() {
+        assert_eq!(
+            shorten("<core::slice::iter::Iter<T> as core::iter::traits::iterator::Iterator>::next"),
+            "<Iter<T> as Iterator>::next"
+        );
+        assert_eq!(shorten("sample::main"), "main");
+    }
+
+    #[test]
+    fn inlining_chain() {
+        // abbrevs: 1 - compile unit, 2 - subprogram with code, 3 - abstract subprogram,
+        // 4 - inlined subroutine with low/high pc, 5 - inlined subroutine with ranges
+        let asm = "\
+\t.text
+outer:
+.Lfunc_begin0:
+\tpush rax
+.Ltmp0:
+\tmov eax, 1
+.Ltmp1:
+\tadd eax, 1
+.Ltmp2:
+\tpop rcx
+\tret
+.Lfunc_end0:
+\t.section\t.debug_abbrev,\"\",@progbits
+\t.byte\t1
+\t.byte\t17
+\t.byte\t1
+\t.byte\t0
+\t.byte\t0
+\t.byte\t2
+\t.byte\t46
+\t.byte\t1
+\t.byte\t17
+\t.byte\t1
+\t.byte\t3
+\t.byte\t14
+\t.byte\t0
+\t.byte\t0
+\t.byte\t3
+\t.byte\t46
+\t.byte\t0
+\t.byte\t110
+\t.byte\t14
+\t.byte\t0
+\t.byte\t0
+\t.byte\t4
+\t.byte\t29
+\t.byte\t1
+\t.byte\t49
+\t.byte\t19
+\t.byte\t17
+\t.byte\t1
+\t.byte\t18
+\t.byte\t6
+\t.byte\t89
+\t.byte\t11
+\t.byte\t0
+\t.byte\t0
+\t.byte\t5
+\t.byte\t29
+\t.byte\t0
+\t.byte\t49
+\t.byte\t19
+\t.byte\t85
+\t.byte\t23
+\t.byte\t89
+\t.byte\t15
+\t.byte\t0
+\t.byte\t0
+\t.byte\t0
+\t.section\t.debug_info,\"\",@progbits
+.Lcu_begin0:
+\t.long\t.Ldebug_info_end0-.Ldebug_info_start0
+.Ldebug_info_start0:
+\t.short\t4
+\t.long\t.debug_abbrev
+\t.byte\t8
+\t.byte\t1
+\t.byte\t3
+\t.long\t.Linfo_string0
+\t.byte\t3
+\t.long\t.Linfo_string1
+\t.byte\t2
+\t.quad\t.Lfunc_begin0
+\t.long\t.Linfo_string2
+\t.byte\t4
+\t.long\t12
+\t.quad\t.Ltmp0
+\t.long\t.Ltmp2-.Ltmp0
+\t.byte\t42
+\t.byte\t5
+\t.long\t17
+\t.long\t.Ldebug_ranges0
+\t.ascii\t\"\\220\\003\"
+\t.byte\t0
+\t.byte\t0
+\t.byte\t0
+.Ldebug_info_end0:
+\t.section\t.debug_ranges,\"\",@progbits
+.Ldebug_ranges0:
+\t.quad\t.Ltmp1
+\t.quad\t.Ltmp2
+\t.quad\t0
+\t.quad\t0
+\t.section\t.debug_str,\"MS\",@progbits,1
+.Linfo_string0:
+\t.asciz\t\"_ZN4core4iter6traits8iterator8Iterator4next17h0123456789abcdefE\"
+.Linfo_string1:
+\t.asciz\t\"_ZN4core5slice4iter4next17h0123456789abcdefE\"
+.Linfo_string2:
+\t.asciz\t\"outer\"
+";
+        let stmts = parse_file(asm).unwrap();
+        let info = InlineInfo::new(&stmts);
+        assert!(!info.is_empty());
+
+        let ix = |needle: &str| {
+            stmts
+                .iter()
+                .position(|s| format!("{s}").contains(needle))
+                .unwrap()
+        };
+        assert!(info.chain(ix("push")).is_none());
+
+        let (root, chain) = info.chain(ix("mov")).unwrap();
+        assert_eq!(
+            breadcrumb(root, &chain, 10, false),
+            "outer:42 > Iterator::next:10"
+        );
+
+        let (root, chain) = info.chain(ix("add")).unwrap();
+        assert_eq!(
+            breadcrumb(root, &chain, 130, false),
+            "outer:42 > Iterator::next:400 > next:130"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Arrows connecting jumps to their targets in a gutter left of the code, similar to
+//! `objdump --visualize-jumps`
+use std::{collections::BTreeMap, io::Write};
+
+use owo_colors::{AnsiColors, OwoColorize};
+
+use super::cfg::{flow, jump_target, Flow};
+use super::statements::{Label, Statement};
+use crate::color;
+
+const PALETTE: &[AnsiColors] = &[
+    AnsiColors::Red,
+    AnsiColors::Green,
+    AnsiColors::Yellow,
+    AnsiColors::Blue,
+    AnsiColors::Magenta,
+    AnsiColors::Cyan,
+];
+
+/// Writer that keeps track of the number of lines written so far
+pub struct CountLines<W> {
+    inner: W,
+    pub lines: usize,
+}
+
+impl<W> CountLines<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, lines: 0 }
+    }
+}
+
+impl<W: Write> Write for CountLines<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.lines += buf[..written].iter().filter(|&&b| b == b'\n').count();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrow {
+    /// output line with the jump
+    pub from: usize,
+    /// output line with the label
+    pub to: usize,
+}
+
+impl Arrow {
+    fn top(self) -> usize {
+        self.from.min(self.to)
+    }
+
+    fn bottom(self) -> usize {
+        self.from.max(self.to)
+    }
+}
+
+/// Arrows for all the jumps to local labels in `stmts`
+///
+/// `rows` maps statement indices to output lines they are printed on, jumps to labels
+/// that are not printed are skipped
+#[must_use]
+pub fn find_arrows(stmts: &[Statement], rows: &BTreeMap<usize, usize>) -> Vec<Arrow> {
+    let labels = stmts
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, stmt)| match stmt {
+            Statement::Label(Label { id, .. }) => Some((*id, rows.get(&ix)?)),
+            _ => None,
+        })
+        .collect::<BTreeMap<_, _>>();
+    stmts
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, stmt)| match stmt {
+            Statement::Instruction(instr)
+                if matches!(flow(instr), Some(Flow::Jump | Flow::Branch)) =>
+            {
+                let to = **labels.get(jump_target(instr)?)?;
+                Some(Arrow {
+                    from: *rows.get(&ix)?,
+                    to,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Assign a column to every arrow, shorter arrows go closer to the code
+///
+/// Arrows in the same column never overlap, column 0 is the closest to the code
+fn columns(arrows: &[Arrow]) -> Vec<usize> {
+    let mut order = (0..arrows.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&ix| arrows[ix].bottom() - arrows[ix].top());
+    let mut taken: Vec<Vec<Arrow>> = Vec::new();
+    let mut res = vec![0; arrows.len()];
+    for ix in order {
+        let arrow = arrows[ix];
+        let free = |col: &Vec<Arrow>| {
+            col.iter()
+                .all(|a| a.bottom() < arrow.top() || a.top() > arrow.bottom())
+        };
+        let col = match taken.iter().position(free) {
+            Some(col) => col,
+            None => {
+                taken.push(Vec::new());
+                taken.len() - 1
+            }
+        };
+        taken[col].push(arrow);
+        res[ix] = col;
+    }
+    res
+}
+
+/// Write `text` with arrows drawn in front of every line
+pub fn write_with_arrows(
+    writer: &mut impl Write,
+    text: &str,
+    arrows: &[Arrow],
+) -> std::io::Result<()> {
+    let cols = columns(arrows);
+    let width = cols.iter().max().map_or(0, |max| max * 2 + 3);
+    let lines = text.lines().collect::<Vec<_>>();
+    // character and the arrow it belongs to for every cell of the gutter
+    let mut gutter = vec![vec![(' ', None); width]; lines.len()];
+
+    for (ix, (arrow, col)) in arrows.iter().zip(&cols).enumerate() {
+        let x = width - 3 - col * 2;
+        for row in &mut gutter[arrow.top() + 1..arrow.bottom()] {
+            let cell = &mut row[x];
+            *cell = (if cell.0 == '─' { '┼' } else { '│' }, Some(ix));
+        }
+        for (row, corner) in [(arrow.top(), '┌'), (arrow.bottom(), '└')] {
+            gutter[row][x] = (corner, Some(ix));
+            for cell in &mut gutter[row][x + 1..width - 1] {
+                let c = match cell.0 {
+                    '│' | '┼' => '┼',
+                    ' ' | '─' => '─',
+                    // corners of other arrows ending on the same line
+                    other => other,
+                };
+                *cell = (c, Some(ix));
+            }
+            gutter[row][width - 1] = (if row == arrow.to { '>' } else { '─' }, Some(ix));
+        }
+    }
+
+    for (line, cells) in lines.iter().zip(gutter) {
+        for (c, arrow) in cells {
+            match arrow {
+                Some(ix) => {
+                    let color = PALETTE[ix % PALETTE.len()];
+                    write!(writer, "{}", color!(c, |c| c.color(color)))?;
+                }
+                None => write!(writer, "{c}")?,
+            }
+        }
+        writeln!(writer, "{line}")?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_arrows() {
+    use super::parse_file;
+    let asm = "\
+foo:
+\ttest edi, edi
+\tje .LBB0_2
+.LBB0_1:
+\tadd eax, 1
+\tjne .LBB0_1
+.LBB0_2:
+\tret
+";
+    let stmts = parse_file(asm).unwrap();
+    let rows = (0..stmts.len()).map(|ix| (ix, ix)).collect();
+    let arrows = find_arrows(&stmts, &rows);
+    assert_eq!(arrows, [Arrow { from: 2, to: 6 }, Arrow { from: 5, to: 3 }]);
+
+    let text = "foo:\n\ttest\n\tje .LBB0_2\n.LBB0_1:\n\tadd\n\tjne .LBB0_1\n.LBB0_2:\n\tret\n";
+    let mut out = Vec::new();
+    write_with_arrows(&mut out, text, &arrows).unwrap();
+    // arrows are colored when other tests enable colors
+    assert_eq!(
+        crate::diff::strip_colors(&String::from_utf8(out).unwrap()),
+        "     foo:
+     \ttest
+┌────\tje .LBB0_2
+│ ┌─>.LBB0_1:
+│ │  \tadd
+│ └──\tjne .LBB0_1
+└───>.LBB0_2:
+     \tret
+"
+    );
+}
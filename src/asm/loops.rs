@@ -0,0 +1,365 @@
+//! Natural loops of a function, found via back edges in its control flow graph
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    ops::Range,
+};
+
+use owo_colors::OwoColorize;
+
+use super::cfg::Cfg;
+use super::statements::Statement;
+use crate::color;
+
+#[derive(Debug, Clone)]
+pub struct Loop {
+    /// block all the back edges jump to
+    pub header: usize,
+    /// blocks with back edges to the header
+    pub latches: Vec<usize>,
+    /// all the blocks of the loop, header and latches included
+    pub blocks: BTreeSet<usize>,
+    /// 1 for outermost loops
+    pub depth: usize,
+    /// loop doesn't contain other loops
+    pub innermost: bool,
+}
+
+/// Immediate dominators for every block reachable from the entry block,
+/// computed with the iterative algorithm by Cooper, Harvey and Kennedy
+fn dominators(preds: &[Vec<usize>], succs: &[Vec<usize>]) -> Vec<Option<usize>> {
+    let len = succs.len();
+    let mut postorder = Vec::with_capacity(len);
+    let mut visited = vec![false; len];
+    let mut stack = Vec::new();
+    if len > 0 {
+        visited[0] = true;
+        stack.push((0, 0));
+    }
+    while let Some((block, next)) = stack.last_mut() {
+        if let Some(&succ) = succs[*block].get(*next) {
+            *next += 1;
+            if !visited[succ] {
+                visited[succ] = true;
+                stack.push((succ, 0));
+            }
+        } else {
+            postorder.push(*block);
+            stack.pop();
+        }
+    }
+    let mut order = vec![usize::MAX; len];
+    for (ix, block) in postorder.iter().enumerate() {
+        order[*block] = ix;
+    }
+
+    let mut idom: Vec<Option<usize>> = vec![None; len];
+    if len > 0 {
+        idom[0] = Some(0);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in postorder.iter().rev().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            for &pred in &preds[block] {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(mut other) => {
+                        let mut pred = pred;
+                        while pred != other {
+                            while order[pred] < order[other] {
+                                pred = idom[pred].expect("processed blocks have dominators");
+                            }
+                            while order[other] < order[pred] {
+                                other = idom[other].expect("processed blocks have dominators");
+                            }
+                        }
+                        pred
+                    }
+                });
+            }
+            if new_idom.is_some() && idom[block] != new_idom {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn dominates(idom: &[Option<usize>], dom: usize, mut block: usize) -> bool {
+    loop {
+        if block == dom {
+            return true;
+        }
+        match idom[block] {
+            Some(parent) if parent != block => block = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// Find natural loops, loops sharing a header are merged together
+#[must_use]
+pub fn find_loops(cfg: &Cfg) -> Vec<Loop> {
+    let len = cfg.blocks.len();
+    let mut preds = vec![Vec::new(); len];
+    let mut succs = vec![Vec::new(); len];
+    for edge in &cfg.edges {
+        preds[edge.to].push(edge.from);
+        succs[edge.from].push(edge.to);
+    }
+    let idom = dominators(&preds, &succs);
+
+    let mut loops = BTreeMap::<usize, Loop>::new();
+    for edge in &cfg.edges {
+        if idom[edge.from].is_none() || !dominates(&idom, edge.to, edge.from) {
+            continue;
+        }
+        let lp = loops.entry(edge.to).or_insert_with(|| Loop {
+            header: edge.to,
+            latches: Vec::new(),
+            blocks: BTreeSet::from([edge.to]),
+            depth: 0,
+            innermost: true,
+        });
+        lp.latches.push(edge.from);
+        let mut stack = vec![edge.from];
+        while let Some(block) = stack.pop() {
+            if lp.blocks.insert(block) {
+                stack.extend(preds[block].iter().filter(|p| idom[**p].is_some()));
+            }
+        }
+    }
+
+    let headers = loops.keys().copied().collect::<Vec<_>>();
+    let mut res = loops.into_values().collect::<Vec<_>>();
+    for ix in 0..res.len() {
+        let header = res[ix].header;
+        res[ix].depth = res.iter().filter(|l| l.blocks.contains(&header)).count();
+        res[ix].innermost = !headers
+            .iter()
+            .any(|h| *h != header && res[ix].blocks.contains(h));
+    }
+    res
+}
+
+impl Loop {
+    /// Statement ranges covered by the loop, adjacent blocks are merged
+    #[must_use]
+    pub fn ranges(&self, cfg: &Cfg) -> Vec<Range<usize>> {
+        let mut res: Vec<Range<usize>> = Vec::new();
+        for block in &self.blocks {
+            let range = cfg.blocks[*block].range.clone();
+            match res.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => res.push(range),
+            }
+        }
+        res
+    }
+}
+
+/// Innermost loop, as shown by `--inner-loops`
+#[derive(Debug, Clone)]
+pub struct InnerLoop<'a> {
+    /// label of the loop header
+    pub header: &'a str,
+    /// 1 for outermost loops
+    pub depth: usize,
+    /// statements of the loop, in the order they appear in the function
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// Innermost loops of a function
+#[must_use]
+pub fn inner_loops<'a>(stmts: &[Statement<'a>]) -> Vec<InnerLoop<'a>> {
+    let cfg = Cfg::new(stmts);
+    find_loops(&cfg)
+        .into_iter()
+        .filter(|lp| lp.innermost)
+        .map(|lp| InnerLoop {
+            header: cfg.blocks[lp.header].labels.first().copied().unwrap_or("?"),
+            depth: lp.depth,
+            ranges: lp.ranges(&cfg),
+        })
+        .collect()
+}
+
+/// Loop structure of a function, by statement
+#[derive(Debug, Default)]
+pub struct LoopMarks {
+    /// loop nesting depth for statements inside of loops
+    depth: BTreeMap<usize, usize>,
+    /// depth of the loop for statements starting loop headers
+    headers: BTreeMap<usize, usize>,
+    /// depth of the loop for jumps back to the loop header
+    latches: BTreeMap<usize, usize>,
+}
+
+impl LoopMarks {
+    #[must_use]
+    pub fn new(stmts: &[Statement]) -> Self {
+        let cfg = Cfg::new(stmts);
+        let loops = find_loops(&cfg);
+        let mut res = Self::default();
+        for lp in &loops {
+            // blocks can start with alignment directives, the loop starts at the label itself
+            let header = &cfg.blocks[lp.header];
+            let start = header
+                .range
+                .clone()
+                .find(|ix| matches!(&stmts[*ix], Statement::Label(l) if header.labels.contains(&l.id)))
+                .unwrap_or(header.range.start);
+            for block in &lp.blocks {
+                let range = cfg.blocks[*block].range.clone();
+                let range = if *block == lp.header {
+                    start..range.end
+                } else {
+                    range
+                };
+                for ix in range {
+                    *res.depth.entry(ix).or_default() += 1;
+                }
+            }
+            res.headers.insert(start, lp.depth);
+            for latch in &lp.latches {
+                let range = cfg.blocks[*latch].range.clone();
+                if let Some(last) = range.clone().rev().find(|ix| stmts[*ix].is_instruction()) {
+                    res.latches.insert(last, lp.depth);
+                }
+            }
+        }
+        res
+    }
+
+    /// Depth of the loop if statement `ix` starts a loop header
+    #[must_use]
+    pub fn header(&self, ix: usize) -> Option<usize> {
+        self.headers.get(&ix).copied()
+    }
+
+    /// Write `text` with a margin showing loop nesting, `rows` maps statement indices to lines
+    ///
+    /// Lines without statements such as Rust source code belong to the statement after them
+    pub fn write_with_margin(
+        &self,
+        writer: &mut impl Write,
+        text: &str,
+        rows: &BTreeMap<usize, usize>,
+    ) -> std::io::Result<()> {
+        let width = self.depth.values().max().copied().unwrap_or(0);
+        let by_row = rows
+            .iter()
+            .map(|(ix, row)| (*row, *ix))
+            .collect::<BTreeMap<_, _>>();
+        for (row, line) in text.lines().enumerate() {
+            let ix = by_row.range(row..).next().map(|(_, ix)| *ix);
+            let mut depth = ix.and_then(|ix| self.depth.get(&ix)).copied().unwrap_or(0);
+            let first_row = ix.and_then(|ix| rows.get(&ix)) == Some(&row);
+            // blank lines before the header label are not part of its loop yet
+            if ix.is_some_and(|ix| !first_row && self.headers.contains_key(&ix)) {
+                depth = depth.saturating_sub(1);
+            }
+            let mut margin = "┃".repeat(depth);
+            if let Some(ix) = ix.filter(|_| first_row) {
+                if self.headers.contains_key(&ix) {
+                    margin.pop();
+                    margin.push('┏');
+                } else if self.latches.contains_key(&ix) {
+                    margin.pop();
+                    margin.push('┗');
+                }
+            }
+            let pad = " ".repeat(width - depth + usize::from(width > 0));
+            writeln!(
+                writer,
+                "{}{pad}{line}",
+                color!(margin, OwoColorize::magenta)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_find_loops() {
+    use super::parse_file;
+    let asm = "\
+foo:
+\ttest edi, edi
+\tje .LBB0_4
+.LBB0_1:
+\tmov ecx, 0
+.LBB0_2:
+\tadd ecx, 1
+\tcmp ecx, 10
+\tjne .LBB0_2
+\tsub edi, 1
+\tjne .LBB0_1
+.LBB0_4:
+\tret
+";
+    let stmts = parse_file(asm).unwrap();
+    let cfg = Cfg::new(&stmts);
+    let loops = find_loops(&cfg)
+        .into_iter()
+        .map(|l| {
+            let labels = cfg.blocks[l.header].labels.clone();
+            (labels, l.blocks.len(), l.depth, l.innermost)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        loops,
+        [
+            (vec![".LBB0_1"], 3, 1, false),
+            (vec![".LBB0_2"], 1, 2, true)
+        ]
+    );
+}
+
+#[test]
+fn test_margin_starts_at_header() {
+    use super::parse_file;
+    let asm = "\
+foo:
+\txor eax, eax
+\t.p2align 4, 0x90
+.LBB0_1:
+\tadd eax, edi
+\tsub edi, 1
+\tjne .LBB0_1
+\tret
+";
+    let stmts = parse_file(asm).unwrap();
+    let marks = LoopMarks::new(&stmts);
+    let render = |text: &str, rows: &BTreeMap<usize, usize>| {
+        let mut out = Vec::new();
+        marks.write_with_margin(&mut out, text, rows).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let before_header = |out: &str| {
+        let lines = out.lines().collect::<Vec<_>>();
+        let header = lines.iter().position(|l| l.contains(".LBB0_1:")).unwrap();
+        assert!(lines[header].starts_with('┏'));
+        lines[header - 1].to_owned()
+    };
+
+    // every statement on its own line
+    let rows = (0..stmts.len()).map(|ix| (ix, ix)).collect();
+    let line = before_header(&render(asm, &rows));
+    assert!(line.contains(".p2align") && !line.contains('┃'), "{line:?}");
+
+    // alignment is hidden, a blank line separates the header instead
+    let text = asm.replace("\t.p2align 4, 0x90", "");
+    let rows = (0..stmts.len())
+        .filter(|ix| *ix != 2)
+        .map(|ix| (ix, ix))
+        .collect();
+    let line = before_header(&render(&text, &rows));
+    assert!(!line.contains('┃'), "{line:?}");
+}
@@ -0,0 +1,72 @@
+//! Calls to panicking functions: failed bounds checks, unwraps, overflows, etc.
+use super::statements::{Loc, Statement};
+use crate::demangle;
+
+/// Functions from `core`, `alloc` and `std` that end up panicking
+const PANICKING: &[&str] = &[
+    "core::panicking::",
+    "core::slice::index::slice_",
+    "core::str::slice_error_fail",
+    "core::result::unwrap_failed",
+    "core::option::unwrap_failed",
+    "core::option::expect_failed",
+    "core::option::expect_none_failed",
+    "core::cell::panic_already_",
+    "alloc::raw_vec::capacity_overflow",
+    "alloc::raw_vec::handle_error",
+    "alloc::alloc::handle_alloc_error",
+    // `begin_panic_handler` too, the rest of `std::panicking` is `catch_unwind` machinery
+    "std::panicking::begin_panic",
+    "std::panicking::rust_panic_with_hook",
+    "std::rt::begin_panic",
+];
+
+#[must_use]
+pub fn is_panicking(name: &str) -> bool {
+    PANICKING.iter().any(|prefix| name.starts_with(prefix))
+}
+
+#[derive(Debug, Clone)]
+pub struct Panic<'a> {
+    /// demangled name of panicking function
+    pub name: String,
+    /// location in rust sources reported by the last `.loc` directive
+    pub loc: Option<Loc<'a>>,
+}
+
+/// Find all the instructions that call (or jump to) panicking functions
+#[must_use]
+pub fn find_panics<'a>(stmts: &[Statement<'a>]) -> Vec<Panic<'a>> {
+    let mut res = Vec::new();
+    let mut loc = None;
+    for stmt in stmts {
+        match stmt {
+            Statement::Directive(super::Directive::Loc(l)) => {
+                loc = (l.line != 0).then_some(*l);
+            }
+            Statement::Instruction(instr) => {
+                let Some(args) = instr.args else { continue };
+                for name in demangle::symbols(args).filter(|name| is_panicking(name)) {
+                    res.push(Panic { name, loc });
+                }
+            }
+            _ => {}
+        }
+    }
+    res
+}
+
+#[test]
+fn test_is_panicking() {
+    assert!(is_panicking("core::panicking::panic_bounds_check"));
+    assert!(is_panicking("core::slice::index::slice_end_index_len_fail"));
+    assert!(is_panicking("core::result::unwrap_failed"));
+    assert!(!is_panicking("core::slice::sort::insertion_sort"));
+    assert!(!is_panicking("alloc::raw_vec::RawVec<T,A>::grow_one"));
+    assert!(is_panicking("std::panicking::begin_panic"));
+    assert!(is_panicking("std::panicking::begin_panic_handler"));
+    assert!(is_panicking("std::panicking::rust_panic_with_hook"));
+    // `catch_unwind` doesn't panic
+    assert!(!is_panicking("std::panicking::try"));
+    assert!(!is_panicking("std::panicking::try::do_call"));
+}
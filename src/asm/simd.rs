@@ -0,0 +1,238 @@
+//! Vector and scalar instructions, used by `--simd-report` to check if code got vectorized
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use super::calls::is_call_like;
+use super::cfg::{flow, Cfg};
+use super::loops::find_loops;
+use super::statements::{Directive, Instruction, Loc, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Class {
+    /// general purpose registers, memory or scalar floating point
+    Scalar,
+    /// fixed width vector registers, width in bits
+    Vector(u32),
+    /// SVE scalable vector registers
+    Scalable,
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Class::Scalar => f.write_str("scalar"),
+            Class::Vector(bits) => write!(f, "{bits} bits"),
+            Class::Scalable => f.write_str("scalable"),
+        }
+    }
+}
+
+/// Width of a vector register operand, `None` for anything else
+///
+/// `xmm`/`ymm`/`zmm` on x86, `v0.4s`/`q0` for NEON and `z0.s`/`p0.b` for SVE
+fn register_width(reg: &str) -> Option<Class> {
+    let digits_after = |prefix: &str| {
+        reg.strip_prefix(prefix)
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+    };
+    for (prefix, bits) in [("xmm", 128), ("ymm", 256), ("zmm", 512)] {
+        if digits_after(prefix).is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit())) {
+            return Some(Class::Vector(bits));
+        }
+    }
+    if let Some(rest) = digits_after("z").or_else(|| digits_after("p")) {
+        if rest.contains('.') {
+            return Some(Class::Scalable);
+        }
+    }
+    if let Some(rest) = digits_after("v") {
+        let (num, arrangement) = rest.split_once('.')?;
+        num.parse::<u8>().ok()?;
+        return Some(Class::Vector(match arrangement {
+            "8b" | "4h" | "2s" | "1d" => 64,
+            _ => 128,
+        }));
+    }
+    if let Some(rest) = digits_after("q") {
+        return rest.parse::<u8>().is_ok().then_some(Class::Vector(128));
+    }
+    None
+}
+
+/// x86 instructions using vector registers to work with a single value
+fn is_scalar_x86(op: &str) -> bool {
+    let op = op.strip_prefix('v').unwrap_or(op);
+    if op.starts_with('p') || op.contains("broadcast") {
+        return false;
+    }
+    let is_conversion = op.starts_with("cvt")
+        && ["ss2", "sd2", "sh2", "si2"]
+            .iter()
+            .any(|from| op.contains(from));
+    is_conversion
+        || matches!(op, "movd" | "movq")
+        || op.ends_with("ss")
+        || op.ends_with("sd")
+        || op.ends_with("sh")
+}
+
+/// Classify an instruction by the widest vector register it uses
+#[must_use]
+pub fn classify(instr: &Instruction) -> Class {
+    // wasm SIMD instructions are named after the lane types
+    if let Some((lanes, _)) = instr.op.split_once('.') {
+        if matches!(
+            lanes,
+            "v128" | "i8x16" | "i16x8" | "i32x4" | "i64x2" | "f32x4" | "f64x2"
+        ) {
+            return Class::Vector(128);
+        }
+    }
+    let widest = instr
+        .args
+        .unwrap_or_default()
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .filter_map(register_width)
+        .max();
+    match widest {
+        Some(Class::Vector(128 | 256 | 512)) if is_scalar_x86(instr.op) => Class::Scalar,
+        Some(class) => class,
+        None => Class::Scalar,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SimdReport<'a> {
+    /// number of instructions by class
+    pub classes: BTreeMap<Class, usize>,
+    /// number of scalar instructions inside of loops, by source line
+    pub scalar_in_loops: Vec<(Loc<'a>, usize)>,
+}
+
+impl SimdReport<'_> {
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.classes.values().sum()
+    }
+
+    #[must_use]
+    pub fn vector(&self) -> usize {
+        self.total() - self.classes.get(&Class::Scalar).copied().unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn widest(&self) -> Option<Class> {
+        self.classes
+            .keys()
+            .copied()
+            .filter(|c| *c != Class::Scalar)
+            .max()
+    }
+}
+
+/// Classify instructions of `functions`, loops are detected separately for every function
+#[must_use]
+pub fn report<'a>(
+    stmts: &[Statement<'a>],
+    functions: impl Iterator<Item = Range<usize>>,
+) -> SimdReport<'a> {
+    let mut res = SimdReport::default();
+    let mut scalar_in_loops = BTreeMap::<(u64, u64), (Loc, usize)>::new();
+    for range in functions {
+        let stmts = &stmts[range];
+        let cfg = Cfg::new(stmts);
+        let in_loops = find_loops(&cfg)
+            .iter()
+            .flat_map(|lp| &lp.blocks)
+            .flat_map(|block| cfg.blocks[*block].range.clone())
+            .collect::<BTreeSet<_>>();
+
+        let mut loc = None;
+        for (ix, stmt) in stmts.iter().enumerate() {
+            match stmt {
+                Statement::Directive(Directive::Loc(l)) => loc = (l.line != 0).then_some(*l),
+                Statement::Instruction(instr) if stmt.is_instruction() => {
+                    let class = classify(instr);
+                    *res.classes.entry(class).or_default() += 1;
+                    // loop control and calls are scalar no matter what
+                    let compute = flow(instr).is_none() && !is_call_like(instr);
+                    if let Some(loc) =
+                        loc.filter(|_| class == Class::Scalar && compute && in_loops.contains(&ix))
+                    {
+                        scalar_in_loops
+                            .entry((loc.file, loc.line))
+                            .or_insert((loc, 0))
+                            .1 += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    res.scalar_in_loops = scalar_in_loops.into_values().collect();
+    res
+}
+
+#[test]
+fn test_classify() {
+    let cases = [
+        (
+            "mulps",
+            "xmm0, xmmword ptr [rip + .LCPI0_0]",
+            Class::Vector(128),
+        ),
+        ("mulss", "xmm0, dword ptr [rdi]", Class::Scalar),
+        ("vaddps", "ymm0, ymm1, ymm2", Class::Vector(256)),
+        ("vfmadd231sd", "xmm0, xmm1, xmm2", Class::Scalar),
+        ("vpmaxsd", "zmm0, zmm1, zmm2", Class::Vector(512)),
+        ("vbroadcastss", "ymm0, dword ptr [rdi]", Class::Vector(256)),
+        ("cvtsi2sd", "xmm0, rax", Class::Scalar),
+        ("addps", "%xmm1, %xmm0", Class::Vector(128)),
+        ("add", "rax, qword ptr [rdi + 8]", Class::Scalar),
+        ("fadd", "v0.4s, v0.4s, v1.4s", Class::Vector(128)),
+        ("add", "v0.2s, v0.2s, v1.2s", Class::Vector(64)),
+        ("ldr", "q0, [x0, #16]", Class::Vector(128)),
+        ("fadd", "s0, s0, s1", Class::Scalar),
+        ("fadd", "z0.s, p0/m, z0.s, z1.s", Class::Scalable),
+        ("f32x4.mul", "", Class::Vector(128)),
+    ];
+    for (op, args, class) in cases {
+        let instr = Instruction {
+            op,
+            args: Some(args),
+        };
+        assert_eq!(classify(&instr), class, "{op} {args}");
+    }
+}
+
+#[test]
+fn test_report() {
+    use super::parse_file;
+    let asm = "\
+foo:
+\t.loc\t1 2 0
+\txor eax, eax
+\t#APP
+\t#NO_APP
+.LBB0_1:
+\t.loc\t1 3 0
+\tmovups xmm0, xmmword ptr [rdi + rax]
+\taddps xmm0, xmm1
+\tadd rax, 16
+\tcmp rax, rsi
+\tjne .LBB0_1
+\tret
+";
+    let stmts = parse_file(asm).unwrap();
+    let report = report(&stmts, std::iter::once(0..stmts.len()));
+    assert_eq!((report.total(), report.vector()), (7, 2));
+    assert_eq!(report.widest(), Some(Class::Vector(128)));
+    let scalar = report
+        .scalar_in_loops
+        .iter()
+        .map(|(loc, count)| (loc.line, *count))
+        .collect::<Vec<_>>();
+    assert_eq!(scalar, [(3, 2)]);
+}
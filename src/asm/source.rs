@@ -0,0 +1,171 @@
+//! Mapping between generated code and lines of Rust source code
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Range, RangeInclusive},
+    path::Path,
+};
+
+use super::statements::{Directive, Statement};
+use crate::Item;
+
+/// Indices of `.file` directives pointing to `path`
+///
+/// `path` can be relative, `src/lib.rs` matches any file ending with those components
+#[must_use]
+pub fn file_indices(stmts: &[Statement], path: &Path) -> BTreeSet<u64> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Directive(Directive::File(f)) => {
+                crate::is_source_file(&f.path.as_full_path(), path).then_some(f.index)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Functions with code generated from `line` of `path` according to `.loc` directives
+#[must_use]
+pub fn items_for_line<'a>(
+    stmts: &[Statement],
+    items: &'a BTreeMap<Item, Range<usize>>,
+    path: &Path,
+    line: u64,
+) -> Vec<&'a Item> {
+    let files = &file_indices(stmts, path);
+    crate::items_for_line(items, line, |range| {
+        stmts[range].iter().filter_map(move |stmt| match stmt {
+            Statement::Directive(Directive::Loc(loc))
+                if loc.line != 0 && files.contains(&loc.file) =>
+            {
+                Some(loc.line)
+            }
+            _ => None,
+        })
+    })
+}
+
+/// Instructions grouped by the source line they are attributed to by `.loc` directives
+#[derive(Debug, Clone, Default)]
+pub struct ByLine<'a> {
+    /// File index and line number to instructions, in order they appear in the code
+    pub lines: BTreeMap<(u64, u64), Vec<&'a Statement<'a>>>,
+    /// Instructions without a location, either before the first `.loc` or after `.loc` with
+    /// line 0 the compiler uses for generated code
+    pub unknown: Vec<&'a Statement<'a>>,
+    /// File index and line the first `.loc` points to, usually the function signature
+    pub first: Option<(u64, u64)>,
+}
+
+impl<'a> ByLine<'a> {
+    #[must_use]
+    pub fn new(stmts: &'a [Statement<'a>]) -> Self {
+        let mut res = Self::default();
+        let mut current = None;
+        for stmt in stmts {
+            match stmt {
+                Statement::Directive(Directive::Loc(loc)) => {
+                    current = (loc.line != 0).then_some((loc.file, loc.line));
+                    if res.first.is_none() {
+                        res.first = current;
+                    }
+                }
+                Statement::Instruction(_) if stmt.is_instruction() => match current {
+                    Some(key) => res.lines.entry(key).or_default().push(stmt),
+                    None => res.unknown.push(stmt),
+                },
+                _ => {}
+            }
+        }
+        res
+    }
+
+    /// Lines of the function itself: from the first location to the last line
+    /// with any instructions in the same file
+    ///
+    /// Code inlined from the same file usually comes from lines outside of this range
+    #[must_use]
+    pub fn main_span(&self) -> Option<(u64, RangeInclusive<u64>)> {
+        let (file, first) = self.first?;
+        let last = self
+            .lines
+            .range((file, first)..=(file, u64::MAX))
+            .next_back()
+            .map_or(first, |l| l.0 .1);
+        Some((file, first..=last))
+    }
+}
+
+#[test]
+fn test_by_line() {
+    use super::parse_file;
+    let asm = "\
+foo:
+\tpush rbp
+\t.loc\t1 10 0
+\tmov eax, 2
+\t.loc\t1 3 0
+\tmov eax, 1
+\t.loc\t2 500 0
+\tadd eax, 1
+\t.loc\t1 14 0
+\timul eax, eax
+\t.loc\t1 0 0
+\tpop rbp
+\t.loc\t1 10 4
+\tret
+";
+    let stmts = parse_file(asm).unwrap();
+    let by_line = ByLine::new(&stmts);
+    let counts = by_line
+        .lines
+        .iter()
+        .map(|(key, instrs)| (*key, instrs.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counts,
+        [((1, 3), 1), ((1, 10), 2), ((1, 14), 1), ((2, 500), 1)]
+    );
+    assert_eq!(by_line.unknown.len(), 2);
+    assert_eq!(by_line.first, Some((1, 10)));
+    assert_eq!(by_line.main_span(), Some((1, 10..=14)));
+}
+
+#[test]
+fn test_items_for_line() {
+    use super::{find_items, parse_file};
+    let asm = "\
+\t.file\t1 \"/home/user/sample\" \"src/lib.rs\"
+\t.file\t2 \"/rustc/abcdef/library/core/src/num/mod.rs\"
+\t.section\t.text.foo,\"ax\",@progbits
+foo:
+\t.loc\t1 10 0
+\tmov eax, 1
+\t.loc\t2 500 0
+\tadd eax, 1
+\t.loc\t1 14 0
+\tret
+.Lfunc_end0:
+\t.section\t.text.bar,\"ax\",@progbits
+bar:
+\t.loc\t1 20 0
+\tmov eax, 1
+\t.loc\t1 12 0
+\tret
+.Lfunc_end1:
+";
+    let stmts = parse_file(asm).unwrap();
+    let items = find_items(&stmts);
+    let names = |path: &str, line| {
+        items_for_line(&stmts, &items, Path::new(path), line)
+            .into_iter()
+            .map(|i| i.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("src/lib.rs", 10), ["foo"]);
+    assert_eq!(names("src/lib.rs", 12), ["bar"]);
+    assert_eq!(names("src/lib.rs", 13), ["bar", "foo"]);
+    assert_eq!(names("core/src/num/mod.rs", 500), ["foo"]);
+    assert!(names("src/main.rs", 10).is_empty());
+    assert!(names("lib.rs", 30).is_empty());
+}
@@ -269,7 +269,7 @@ pub struct File<'a> {
     pub md5: Option<&'a str>,
 }
 
-fn parse_quoted_string(input: &str) -> IResult<&str, String> {
+pub(crate) fn parse_quoted_string(input: &str) -> IResult<&str, String> {
     // Inverse of MCAsmStreamer::PrintQuotedString() in MCAsmStreamer.cpp in llvm.
     delimited(
         tag("\""),
@@ -636,7 +636,7 @@ pub enum Directive<'a> {
 #[derive(Clone, Debug)]
 pub struct GenericDirective<'a>(pub &'a str);
 
-pub fn parse_statement(input: &str) -> IResult<&str, Statement> {
+pub fn parse_statement(input: &str) -> IResult<&str, Statement<'_>> {
     let label = map(Label::parse, Statement::Label);
 
     let file = map(File::parse, Directive::File);
@@ -691,6 +691,11 @@ impl Statement<'_> {
         matches!(self, Statement::Label(Label { id, .. }) if check_id(id))
     }
 
+    /// Actual instruction rather than a comment
+    pub(crate) fn is_instruction(&self) -> bool {
+        matches!(self, Statement::Instruction(i) if !i.op.starts_with('#'))
+    }
+
     pub(crate) fn is_section_start(&self) -> bool {
         matches!(self, Statement::Directive(Directive::SectionStart(_)))
     }
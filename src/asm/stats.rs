@@ -0,0 +1,270 @@
+//! Instruction mix of a function or a whole file: opcodes, memory accesses, control flow
+//! and the stack frame size
+use std::{collections::BTreeMap, ops::Range};
+
+use super::calls::is_call_like;
+use super::cfg::{flow, jump_target, Flow};
+use super::statements::{Instruction, Statement};
+
+#[derive(Debug, Default)]
+pub struct Stats<'a> {
+    pub instructions: usize,
+    /// number of instructions by opcode
+    pub opcodes: BTreeMap<&'a str, usize>,
+    pub loads: usize,
+    pub stores: usize,
+    /// jumps to local labels, conditional or not
+    pub branches: usize,
+    /// calls and tail calls
+    pub calls: usize,
+    /// instructions changing the stack pointer
+    pub stack_adjustments: usize,
+    /// bytes reserved on the stack by the prologue, the largest one for several functions
+    pub frame_size: u64,
+}
+
+/// Operands separated by commas, commas inside of brackets and parens are kept
+///
+/// Trailing comments such as `# imm = 0x1000` or `// 8-byte Spill` are dropped
+fn operands(args: &str) -> Vec<&str> {
+    let args = [" # ", "\t# ", " // ", "\t// "]
+        .iter()
+        .filter_map(|comment| args.find(comment))
+        .min()
+        .map_or(args, |end| &args[..end]);
+    let mut res = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    for (ix, c) in args.char_indices() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(args[start..ix].trim());
+                start = ix + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(args[start..].trim());
+    res
+}
+
+/// Immediate value: `56`, `$56`, `#-32` or `0x38`
+fn immediate(arg: &str) -> Option<i64> {
+    let arg = arg.trim().trim_start_matches(['$', '#']);
+    let (negative, arg) = match arg.strip_prefix('-') {
+        Some(arg) => (true, arg),
+        None => (false, arg),
+    };
+    let value = match arg.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => arg.parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_stack_pointer(arg: &str) -> bool {
+    matches!(arg.trim_start_matches('%'), "rsp" | "esp" | "sp" | "wsp")
+}
+
+/// Memory access kind of an instruction: (load, store)
+fn memory_access(instr: &Instruction) -> (bool, bool) {
+    let op = instr.op;
+    let Some(args) = instr.args else {
+        return (false, false);
+    };
+    let ops = operands(args);
+    match op {
+        // x86, AT&T syntax adds size suffixes
+        "push" | "pushq" | "pushl" => return (ops.iter().any(|o| o.contains('[')), true),
+        "pop" | "popq" | "popl" => return (true, ops.iter().any(|o| o.contains('['))),
+        // riscv
+        "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" | "flw" | "fld" => return (true, false),
+        "sb" | "sh" | "sw" | "sd" | "fsw" | "fsd" => return (false, true),
+        _ => {}
+    }
+    if is_call_like(instr) || op.starts_with("lea") || op.starts_with("nop") {
+        return (false, false);
+    }
+    // aarch64, loads and stores are the only instructions accessing memory
+    if args.contains('[') && !args.contains("ptr") {
+        return (op.starts_with("ld"), op.starts_with("st"));
+    }
+
+    // x86: memory operands are `qword ptr [rdi]` or `8(%rdi)`, AT&T puts destination last
+    let att = args.contains('%');
+    let is_memory = |o: &&str| {
+        if att {
+            o.contains('(')
+        } else {
+            o.contains('[')
+        }
+    };
+    let (dest, sources) = match (att, ops.split_last(), ops.split_first()) {
+        (true, Some((dest, sources)), _) | (false, _, Some((dest, sources))) => (dest, sources),
+        _ => return (false, false),
+    };
+    let reads_dest = !(op.starts_with("mov") || op.starts_with("vmov") || op.starts_with("set"));
+    let writes_dest = !(op.starts_with("cmp") || op.starts_with("test"));
+    let dest_mem = is_memory(dest);
+    (
+        sources.iter().any(is_memory) || (dest_mem && reads_dest),
+        dest_mem && writes_dest,
+    )
+}
+
+/// Bytes the instruction reserves on the stack, negative for instructions releasing it,
+/// `None` for instructions that don't touch the stack pointer
+fn stack_adjustment(instr: &Instruction) -> Option<i64> {
+    let ops = operands(instr.args?);
+    match (instr.op, ops.as_slice()) {
+        // x86
+        ("push" | "pushq", _) => Some(8),
+        ("pushl", _) => Some(4),
+        ("pop" | "popq", _) => Some(-8),
+        ("popl", _) => Some(-4),
+        ("sub" | "subq" | "subl", [sp, value]) if is_stack_pointer(sp) => immediate(value),
+        ("add" | "addq" | "addl", [sp, value]) if is_stack_pointer(sp) => {
+            immediate(value).map(|v| -v)
+        }
+        ("subq" | "subl", [value, sp]) if is_stack_pointer(sp) => immediate(value),
+        ("addq" | "addl", [value, sp]) if is_stack_pointer(sp) => immediate(value).map(|v| -v),
+        // aarch64 and riscv
+        ("sub" | "add" | "addi", [sp, sp2, value, shift @ ..])
+            if is_stack_pointer(sp) && is_stack_pointer(sp2) =>
+        {
+            let mut value = immediate(value)?;
+            if let [shift] = shift {
+                value <<= immediate(shift.trim_start_matches("lsl"))?;
+            }
+            Some(if instr.op == "sub" { value } else { -value })
+        }
+        // aarch64 pre- and post-indexed addressing: `stp ..., [sp, #-16]!` and `ldp ..., [sp], #16`
+        (_, [.., mem]) if mem.starts_with("[sp,") && mem.ends_with("]!") => {
+            let offset = mem.trim_start_matches("[sp,").trim_end_matches("]!");
+            immediate(offset).map(|v| -v)
+        }
+        (_, [.., mem, offset]) if *mem == "[sp]" => immediate(offset).map(|v| -v),
+        _ => None,
+    }
+}
+
+impl<'a> Stats<'a> {
+    /// Collect statistics for `functions`, they are counted together
+    #[must_use]
+    pub fn new(stmts: &[Statement<'a>], functions: impl Iterator<Item = Range<usize>>) -> Self {
+        let mut res = Self::default();
+        for range in functions {
+            let mut frame = 0;
+            // prologue ends with the first call or jump
+            let mut in_prologue = true;
+            for stmt in &stmts[range] {
+                let Statement::Instruction(instr) = stmt else {
+                    continue;
+                };
+                // skip comments such as `#APP`
+                if !stmt.is_instruction() {
+                    continue;
+                }
+                res.instructions += 1;
+                *res.opcodes.entry(instr.op).or_default() += 1;
+                let (load, store) = memory_access(instr);
+                res.loads += usize::from(load);
+                res.stores += usize::from(store);
+                let local_jump = jump_target(instr).is_some()
+                    && matches!(flow(instr), Some(Flow::Jump | Flow::Branch));
+                if local_jump {
+                    res.branches += 1;
+                } else if is_call_like(instr) {
+                    res.calls += 1;
+                }
+                if let Some(adjustment) = stack_adjustment(instr) {
+                    res.stack_adjustments += 1;
+                    if in_prologue && adjustment > 0 {
+                        frame += adjustment.unsigned_abs();
+                    }
+                }
+                if flow(instr).is_some() || is_call_like(instr) {
+                    in_prologue = false;
+                }
+            }
+            res.frame_size = res.frame_size.max(frame);
+        }
+        res
+    }
+
+    /// Opcodes sorted by the number of uses, most used first
+    #[must_use]
+    pub fn histogram(&self) -> Vec<(&'a str, usize)> {
+        let mut res = self
+            .opcodes
+            .iter()
+            .map(|(op, count)| (*op, *count))
+            .collect::<Vec<_>>();
+        res.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        res
+    }
+}
+
+#[test]
+fn test_stats() {
+    use super::parse_file;
+    let x86 = "\
+foo:
+# %bb.0:
+\tpush rbx
+\tsub rsp, 48
+\t#APP
+\t#NO_APP
+\tmov rax, qword ptr [rdi + 8]
+\tmov qword ptr [rsp + 8], rax
+\tadd dword ptr [rdi], 1
+\tlea rdi, [rsp + 8]
+\tcall bar
+\ttest eax, eax
+\tjne .LBB0_2
+.LBB0_1:
+\tadd rsp, 48
+\tpop rbx
+\tret
+.LBB0_2:
+\tjmp .LBB0_1
+";
+    let stmts = parse_file(x86).unwrap();
+    let stats = Stats::new(&stmts, std::iter::once(0..stmts.len()));
+    assert_eq!(stats.instructions, 13);
+    assert_eq!((stats.loads, stats.stores), (3, 3));
+    assert_eq!((stats.branches, stats.calls), (2, 1));
+    assert_eq!((stats.stack_adjustments, stats.frame_size), (4, 56));
+    assert_eq!(
+        stats.histogram()[..3],
+        [("add", 2), ("mov", 2), ("call", 1)]
+    );
+
+    let aarch64 = "\
+foo:
+\tstp x29, x30, [sp, #-32]!
+\tsub sp, sp, #16
+\tldr x8, [x0]
+\tstr x8, [sp, #8]
+\tbl bar
+\tcbz w0, .LBB0_2
+\tadd sp, sp, #16
+\tldp x29, x30, [sp], #32
+\tret
+.LBB0_2:
+\tb .LBB0_2
+";
+    assert_eq!(operands("rsp, 4096  # imm = 0x1000"), ["rsp", "4096"]);
+    assert_eq!(
+        operands("sp, sp, #16\t// 0: ff 43 00 d1"),
+        ["sp", "sp", "#16"]
+    );
+
+    let stmts = parse_file(aarch64).unwrap();
+    let stats = Stats::new(&stmts, std::iter::once(0..stmts.len()));
+    assert_eq!((stats.loads, stats.stores), (2, 2));
+    assert_eq!((stats.branches, stats.calls), (2, 1));
+    assert_eq!((stats.stack_adjustments, stats.frame_size), (4, 48));
+}
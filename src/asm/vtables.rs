@@ -0,0 +1,344 @@
+//! Vtables defined in the file and calls made through them
+//!
+//! rustc emits vtables as anonymous constants: pointer to `drop_in_place`, size, alignment
+//! and pointers to methods, with zero and integer parts often merged into `.ascii` strings.
+//! Only 64 bit targets are supported.
+use std::collections::{BTreeMap, BTreeSet};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::constants::Constants;
+use super::statements::{parse_quoted_string, Instruction, Statement};
+use crate::demangle;
+
+/// Offset of the first method, after `drop_in_place`, size and alignment
+const FIRST_METHOD: usize = 3;
+
+// `qword ptr [rax + 24]` or `*24(%rax)`, only 64 bit registers
+static INDIRECT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:qword ptr \[(r\w+)(?: \+ (\d+))?\]|\*(\d*)\(%(r\w+)\))$")
+        .expect("regexp should be valid")
+});
+
+/// Registers preserved across calls by the System V x86-64 ABI
+const CALLEE_SAVED: &[&str] = &["rbx", "rbp", "r12", "r13", "r14", "r15"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Slot<'a> {
+    Value(u64),
+    Symbol(&'a str),
+}
+
+#[derive(Debug, Clone)]
+pub struct Vtable<'a> {
+    /// local label the vtable is defined under
+    pub label: &'a str,
+    /// mangled name of `drop_in_place`, `None` for types without drop glue
+    pub drop: Option<&'a str>,
+    pub size: u64,
+    pub align: u64,
+    /// mangled names of methods, `None` for methods that can't be called such as ones
+    /// with `where Self: Sized`
+    pub methods: Vec<Option<&'a str>>,
+}
+
+/// Demangled symbol name, hash is only included with `full_name`
+#[must_use]
+pub fn symbol_name(symbol: &str, full_name: bool) -> String {
+    match demangle::demangled(symbol) {
+        Some(dem) if full_name => format!("{dem:?}"),
+        Some(dem) => format!("{dem:#?}"),
+        None => symbol.to_owned(),
+    }
+}
+
+/// `<v::Sq as v::Shape>::area` gives `v::Shape::area`, drop glue gives `core::ptr::drop_in_place`
+fn trait_method(name: &str) -> String {
+    if let Some((for_type, method)) = name.rsplit_once(">::") {
+        if let Some((_, tr)) = for_type.rsplit_once(" as ") {
+            return format!("{tr}::{method}");
+        }
+    }
+    name.find('<').map_or(name, |ix| &name[..ix]).to_owned()
+}
+
+impl Vtable<'_> {
+    /// `<v::Sq as v::Shape>::{vtable}`, based on names of the methods
+    #[must_use]
+    pub fn name(&self) -> String {
+        let method = self.methods.iter().rev().flatten().next();
+        match method
+            .map(|m| symbol_name(m, false))
+            .as_deref()
+            .and_then(|m| m.rsplit_once("::"))
+        {
+            Some((for_type, _)) => format!("{for_type}::{{vtable}}"),
+            None => self.label.to_owned(),
+        }
+    }
+
+    /// Symbol stored at `offset` bytes into the vtable
+    #[must_use]
+    pub fn slot(&self, offset: usize) -> Option<&str> {
+        let (ix, 0) = (offset / 8, offset % 8) else {
+            return None;
+        };
+        match ix {
+            0 => self.drop,
+            1 | 2 => None,
+            ix => *self.methods.get(ix - FIRST_METHOD)?,
+        }
+    }
+}
+
+/// Data split into pointer sized slots, `None` if it contains something other than
+/// numbers, strings and symbols or symbols are not aligned
+fn slots<'a>(data: &[(&'a str, &'a str)]) -> Option<Vec<Slot<'a>>> {
+    let mut slots = Vec::new();
+    let mut bytes = Vec::new();
+    let push_bytes = |bytes: &mut Vec<u8>, slots: &mut Vec<Slot>| {
+        while bytes.len() >= 8 {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[..8]);
+            slots.push(Slot::Value(u64::from_le_bytes(value)));
+            bytes.drain(..8);
+        }
+    };
+    for &(name, args) in data {
+        match name {
+            "ascii" | "asciz" | "string" => {
+                let (_, string) = parse_quoted_string(args).ok()?;
+                // octal escapes produce chars in 0..=255 range, one per byte
+                #[allow(clippy::cast_possible_truncation)]
+                bytes.extend(string.chars().map(|c| c as u8));
+                if name != "ascii" {
+                    bytes.push(0);
+                }
+            }
+            "zero" | "space" => {
+                let (len, fill) = args.split_once(',').unwrap_or((args, "0"));
+                let fill = fill.trim().parse::<u8>().ok()?;
+                let len = len.trim().parse::<usize>().ok()?;
+                bytes.resize(bytes.len() + len, fill);
+            }
+            _ => {
+                let width = match name {
+                    "byte" => 1,
+                    "short" | "value" | "2byte" | "hword" => 2,
+                    "long" | "int" | "4byte" | "word" => 4,
+                    _ => 8,
+                };
+                for arg in args.split(',').map(str::trim) {
+                    #[allow(clippy::cast_sign_loss)]
+                    let value = match arg.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                        None => arg
+                            .parse::<u64>()
+                            .ok()
+                            .or_else(|| arg.parse::<i64>().ok().map(|v| v as u64)),
+                    };
+                    match value {
+                        Some(value) => bytes.extend_from_slice(&value.to_le_bytes()[..width]),
+                        None if width == 8 && bytes.is_empty() => slots.push(Slot::Symbol(arg)),
+                        None => return None,
+                    }
+                }
+            }
+        }
+        push_bytes(&mut bytes, &mut slots);
+    }
+    bytes.is_empty().then_some(slots)
+}
+
+/// Vtables defined in the file: data with a `drop_in_place` or a null pointer, size,
+/// power of two alignment and at least one method
+#[must_use]
+pub fn find_vtables<'a>(constants: &Constants<'a>) -> Vec<Vtable<'a>> {
+    constants
+        .iter()
+        .filter_map(|(label, data)| {
+            let slots = slots(data)?;
+            let [drop, Slot::Value(size), Slot::Value(align), methods @ ..] = slots.as_slice()
+            else {
+                return None;
+            };
+            let drop = match drop {
+                Slot::Value(0) => None,
+                Slot::Symbol(sym)
+                    if symbol_name(sym, false).starts_with("core::ptr::drop_in_place") =>
+                {
+                    Some(*sym)
+                }
+                _ => return None,
+            };
+            let methods = methods
+                .iter()
+                .map(|slot| match slot {
+                    Slot::Value(0) => Some(None),
+                    Slot::Symbol(sym) if demangle::demangled(sym).is_some() => Some(Some(*sym)),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            (align.is_power_of_two() && methods.iter().any(Option::is_some)).then_some(Vtable {
+                label,
+                drop,
+                size: *size,
+                align: *align,
+                methods,
+            })
+        })
+        .collect()
+}
+
+/// Register and offset of an indirect call: `call qword ptr [rax + 24]` gives `("rax", 24)`
+fn indirect_call<'a>(instr: &Instruction<'a>) -> Option<(&'a str, usize)> {
+    if !super::calls::is_call_like(instr) {
+        return None;
+    }
+    let caps = INDIRECT.captures(instr.args?.trim())?;
+    let reg = caps.get(1).or(caps.get(4))?.as_str();
+    let offset = caps.get(2).or(caps.get(3)).map_or("", |m| m.as_str());
+    Some((
+        reg,
+        if offset.is_empty() {
+            0
+        } else {
+            offset.parse().ok()?
+        },
+    ))
+}
+
+/// Destination and source operands, `None` for instructions with other than two operands
+fn operands<'a>(instr: &Instruction<'a>) -> Option<(&'a str, &'a str)> {
+    let (a, b) = instr.args?.split_once(", ")?;
+    if b.contains(", ") {
+        return None;
+    }
+    // AT&T syntax puts the destination last and prefixes registers with `%`
+    Some(if a.contains('%') || b.contains('%') {
+        (b.trim_start_matches('%'), a.trim_start_matches('%'))
+    } else {
+        (a, b)
+    })
+}
+
+/// Likely targets of indirect calls through vtables, by statement index
+///
+/// When a vtable address is loaded into a register in the same basic block the method is
+/// known exactly, otherwise calls at offsets used by vtables in this file are attributed to
+/// the trait method, as long as all of the vtables agree. Such calls are marked as guesses:
+/// nothing says the register holds a vtable pointer
+#[must_use]
+pub fn annotate_calls(
+    stmts: &[Statement],
+    vtables: &[Vtable],
+    full_name: bool,
+) -> BTreeMap<usize, String> {
+    let by_label = vtables
+        .iter()
+        .map(|v| (v.label, v))
+        .collect::<BTreeMap<_, _>>();
+    let mut known = BTreeMap::<&str, &Vtable>::new();
+    let mut res = BTreeMap::new();
+    for (ix, stmt) in stmts.iter().enumerate() {
+        let instr = match stmt {
+            Statement::Instruction(instr) if stmt.is_instruction() => instr,
+            Statement::Label(_) => {
+                known.clear();
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some((reg, offset)) = indirect_call(instr) {
+            if let Some(method) = known.get(reg).and_then(|v| v.slot(offset)) {
+                res.insert(ix, symbol_name(method, full_name));
+            } else {
+                let candidates = vtables
+                    .iter()
+                    .filter_map(|v| v.slot(offset))
+                    .map(|sym| trait_method(&symbol_name(sym, false)))
+                    .collect::<BTreeSet<_>>();
+                if let [method] = candidates.into_iter().collect::<Vec<_>>().as_slice() {
+                    res.insert(ix, format!("dyn {method} (guess)"));
+                }
+            }
+            known.retain(|reg, _| CALLEE_SAVED.contains(reg));
+            continue;
+        }
+        let Some((dst, src)) = operands(instr) else {
+            continue;
+        };
+        let loaded = match instr.op {
+            "lea" | "leaq" => demangle::local_label_names(src).find_map(|l| by_label.get(l)),
+            "mov" | "movq" => known.get(src),
+            _ => None,
+        }
+        .copied();
+        match loaded {
+            Some(vtable) => known.insert(dst, vtable),
+            None => known.remove(dst),
+        };
+    }
+    res
+}
+
+#[test]
+fn test_vtables() {
+    use super::parse_file;
+    let asm = "\
+\t.section\t.text.total,\"ax\",@progbits
+total:
+\tlea\trax, [rip + .Lanon.a44ed067ea593f01f48e2188512e197a.1]
+\tmov\trcx, rax
+\tcall\tqword ptr [rcx + 24]
+\tcall\tqword ptr [rcx + 24]
+\tcall\tqword ptr [rbx + 32]
+\tcall\tqword ptr [rbx]
+\tcall\tdword ptr [ebx + 24]
+\tret
+\t.section\t.data.rel.ro..Lanon.a44ed067ea593f01f48e2188512e197a.0,\"aw\",@progbits
+.Lanon.a44ed067ea593f01f48e2188512e197a.0:
+\t.asciz\t\"\\000\\000\\000\\000\\000\\000\\000\\000\\b\\000\\000\\000\\000\\000\\000\\000\\b\\000\\000\\000\\000\\000\\000\"
+\t.quad\t_ZN34_$LT$v..Sq$u20$as$u20$v..Shape$GT$4area17h22faae0f1636abdfE
+\t.quad\t_ZN34_$LT$v..Sq$u20$as$u20$v..Shape$GT$4name17hbaff72ecd29e19e7E
+\t.size\t.Lanon.a44ed067ea593f01f48e2188512e197a.0, 40
+.Lanon.a44ed067ea593f01f48e2188512e197a.1:
+\t.quad\t_ZN4core3ptr30drop_in_place$LT$v..Circle$GT$17h97b108881968ca1bE
+\t.asciz\t\" \\000\\000\\000\\000\\000\\000\\000\\b\\000\\000\\000\\000\\000\\000\"
+\t.quad\t_ZN38_$LT$v..Circle$u20$as$u20$v..Shape$GT$4area17h77f46502b9412cd0E
+\t.quad\t_ZN38_$LT$v..Circle$u20$as$u20$v..Shape$GT$4name17hd5ad1adc69cb88bfE
+\t.size\t.Lanon.a44ed067ea593f01f48e2188512e197a.1, 40
+.Lanon.a44ed067ea593f01f48e2188512e197a.2:
+\t.ascii\t\"sq\"
+";
+    let stmts = parse_file(asm).unwrap();
+    let constants = Constants::new(&stmts);
+    let vtables = find_vtables(&constants);
+    let names = vtables.iter().map(Vtable::name).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "<v::Sq as v::Shape>::{vtable}",
+            "<v::Circle as v::Shape>::{vtable}"
+        ]
+    );
+    assert_eq!(
+        (vtables[0].drop, vtables[0].size, vtables[0].align),
+        (None, 8, 8)
+    );
+    assert_eq!((vtables[1].size, vtables[1].align), (32, 8));
+
+    let calls = annotate_calls(&stmts, &vtables, false)
+        .into_values()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        [
+            "<v::Circle as v::Shape>::area",
+            "dyn v::Shape::area (guess)",
+            "dyn v::Shape::name (guess)",
+            "dyn core::ptr::drop_in_place (guess)",
+        ]
+    );
+}
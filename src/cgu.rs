@@ -0,0 +1,211 @@
+//! Code split between several codegen units, one file per unit
+//!
+//! Items from all the units are merged so they can be picked and suggested together,
+//! picked item is then dumped from the file of its unit as usual
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    asm,
+    cached_lines::CachedLines,
+    color, disasm, esafeprintln, get_all_matches, get_dump_range, llvm, mir,
+    opts::{Format, Syntax, ToDump},
+    safeprintln, Item,
+};
+
+/// Files with code for every codegen unit, ordered by unit number
+///
+/// rustc names the file `{stem}.{ext}` when there's only one unit and
+/// `{stem}.{crate}.{hash}-cgu.{N}.rcgu.{ext}` otherwise, files last modified before `since`
+/// are left from previous builds and skipped
+///
+/// # Errors
+/// Reports file IO errors
+pub fn unit_files(stem: &Path, ext: &str, since: SystemTime) -> anyhow::Result<Vec<PathBuf>> {
+    let (Some(dir), Some(stem)) = (stem.parent(), stem.file_name().and_then(|s| s.to_str())) else {
+        anyhow::bail!("Can't look for codegen units next to {}", stem.display());
+    };
+    let single = format!("{stem}.{ext}");
+    let suffix = format!(".rcgu.{ext}");
+    let mut res = Vec::new();
+    for entry in dir.read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let is_unit = name
+            .strip_prefix(stem)
+            .is_some_and(|rest| rest.starts_with('.') && rest.ends_with(&suffix));
+        if (name == single || is_unit) && entry.metadata()?.modified()? >= since {
+            res.push(entry.path());
+        }
+    }
+    // cgu.2 goes before cgu.10
+    res.sort_by_key(|path| (path.as_os_str().len(), path.clone()));
+    Ok(res)
+}
+
+/// Short name of a codegen unit: `cgu.3` for `foo-hash.foo.abc123-cgu.3.rcgu.s`
+///
+/// Thin LTO creates a unit for every crate code was imported from, those are named after the
+/// crate: `std/cgu.0` for `foo-hash.std-hash.std.abc123-cgu.0.rcgu.o.rcgu.s`
+#[must_use]
+pub fn unit_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let Some((unit, _)) = name.split_once(".rcgu.") else {
+        return name;
+    };
+    match unit.split('.').collect::<Vec<_>>().as_slice() {
+        [_, _, hash, number] if hash.ends_with("-cgu") => format!("cgu.{number}"),
+        [_, _, krate, hash, number] if hash.ends_with("-cgu") => format!("{krate}/cgu.{number}"),
+        [_, unit] => (*unit).to_owned(),
+        _ => unit.to_owned(),
+    }
+}
+
+fn unit_items(path: &Path, syntax: Syntax) -> anyhow::Result<BTreeMap<Item, Range<usize>>> {
+    let contents = match syntax {
+        Syntax::Disasm => disasm::disassemble(path)?,
+        _ => std::fs::read_to_string(path)?,
+    };
+    Ok(match syntax {
+        Syntax::Llvm | Syntax::LlvmInput => {
+            llvm::find_items(&CachedLines::without_ending(contents))
+        }
+        Syntax::Mir => mir::find_items(&CachedLines::without_ending(contents)),
+        Syntax::Intel
+        | Syntax::Att
+        | Syntax::Wasm
+        | Syntax::McaIntel
+        | Syntax::McaAtt
+        | Syntax::Disasm => asm::find_items(&asm::parse_file(&contents)?),
+    })
+}
+
+/// Goal that picks `item` out of `items` defined in a single unit
+///
+/// Items are picked by their full name and their position among items with this name,
+/// the rest of the goal is kept as is
+fn goal_in_unit(goal: &ToDump, item: &Item, items: &BTreeMap<Item, Range<usize>>) -> ToDump {
+    let nth = items
+        .keys()
+        .take_while(|i| *i != item)
+        .filter(|i| i.hashed == item.hashed || i.name == item.hashed)
+        .count();
+    let with_callees = match goal {
+        ToDump::Function { with_callees, .. } => *with_callees,
+        _ => None,
+    };
+    ToDump::Function {
+        with_callees,
+        regex: false,
+        exact: true,
+        all_matches: false,
+        function: item.hashed.clone(),
+        nth: Some(nth),
+    }
+}
+
+/// Dump `goal` from code split between several units, `dump` handles a single file
+///
+/// Names of items defined in more than one unit are tagged with the unit name so copies
+/// of the same function can be told apart. Reports and `--everything` go through the
+/// units one by one.
+///
+/// # Errors
+/// Reports file IO and parsing errors
+pub fn dump_function(
+    goal: ToDump,
+    paths: &[PathBuf],
+    syntax: Syntax,
+    fmt: &Format,
+    mut dump: impl FnMut(ToDump, &Path) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let header = |name: &str| {
+        let header = format!("==> {name} <==");
+        safeprintln!("{}", color!(header, OwoColorize::bright_black));
+    };
+
+    match goal {
+        ToDump::Everything | ToDump::Report { .. } => {
+            for (ix, path) in paths.iter().enumerate() {
+                if ix > 0 {
+                    safeprintln!();
+                }
+                header(&unit_name(path));
+                dump(goal.clone(), path)?;
+            }
+            return Ok(());
+        }
+        ToDump::Interactive => {
+            anyhow::bail!("Interactive mode needs a single codegen unit");
+        }
+        _ if goal.source_location().is_some() => {
+            anyhow::bail!("Selecting functions by source location needs a single codegen unit");
+        }
+        _ => {}
+    }
+
+    let units = paths
+        .iter()
+        .map(|path| unit_items(path, syntax))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut defined_in = BTreeMap::<&str, BTreeSet<usize>>::new();
+    for (unit, items) in units.iter().enumerate() {
+        for item in items.keys() {
+            defined_in.entry(&item.name).or_default().insert(unit);
+        }
+    }
+
+    // merged item and the unit with its original item
+    let mut merged = BTreeMap::new();
+    let mut origin = BTreeMap::new();
+    for (unit, items) in units.iter().enumerate() {
+        for (item, range) in items {
+            let mut tagged = item.clone();
+            if defined_in[item.name.as_str()].len() > 1 {
+                tagged.name = format!("{} [{}]", item.name, unit_name(&paths[unit]));
+            }
+            merged.insert(tagged.clone(), range.clone());
+            origin.insert(tagged, (unit, item));
+        }
+    }
+
+    let picked = match get_all_matches(&goal, &merged) {
+        Some(matches) => matches,
+        None => get_dump_range(goal.clone(), fmt, merged)
+            .into_iter()
+            .collect(),
+    };
+    for (ix, (item, _)) in picked.iter().enumerate() {
+        let (unit, original) = origin[item];
+        if picked.len() > 1 {
+            if ix > 0 {
+                safeprintln!();
+            }
+            header(&item.name);
+        } else if fmt.verbosity > 0 {
+            esafeprintln!("Found in codegen unit {}", unit_name(&paths[unit]));
+        }
+        dump(goal_in_unit(&goal, original, &units[unit]), &paths[unit])?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unit_name() {
+    let path = Path::new("target/release/deps/foo-0123.foo.860898faace57d02-cgu.12.rcgu.s");
+    assert_eq!(unit_name(path), "cgu.12");
+    assert_eq!(unit_name(Path::new("deps/foo-0123.s")), "foo-0123.s");
+    let path = Path::new("deps/foo-0123.std-4567.std.e28293b1aa0f68bd-cgu.0.rcgu.o.rcgu.s");
+    assert_eq!(unit_name(path), "std/cgu.0");
+    let path = Path::new("deps/foo-0123.2gc9hlkb7emh9hy4mt0klq5xd.rcgu.s");
+    assert_eq!(unit_name(path), "2gc9hlkb7emh9hy4mt0klq5xd");
+}
@@ -11,7 +11,7 @@ pub fn name(input: &str) -> Option<String> {
 }
 
 #[must_use]
-pub fn demangled(input: &str) -> Option<Demangle> {
+pub fn demangled(input: &str) -> Option<Demangle<'_>> {
     let name = if input.starts_with("__") {
         #[allow(clippy::string_slice)]
         rustc_demangle::try_demangle(&input[1..]).ok()?
@@ -63,10 +63,17 @@ pub enum LabelKind {
     Unknown,
 }
 
-pub fn local_labels(input: &str) -> regex::Matches {
+pub fn local_labels(input: &str) -> regex::Matches<'_, '_> {
     LOCAL_LABELS.find_iter(input)
 }
 
+/// Names of local labels mentioned in `input`, without separators matched by [`local_labels`]
+pub fn local_label_names(input: &str) -> impl Iterator<Item = &str> {
+    LOCAL_LABELS
+        .captures_iter(input)
+        .filter_map(|cap| Some(cap.get(1)?.as_str()))
+}
+
 #[must_use]
 pub fn label_kind(input: &str) -> LabelKind {
     match LABEL_KINDS.matches(input).into_iter().next() {
@@ -124,11 +131,21 @@ pub fn contents(input: &str, full_name: bool) -> Cow<'_, str> {
     GLOBAL_LABELS.replace_all(input, Demangler { full_name })
 }
 
+/// Demangled names of all the symbols mentioned in `input`, without hashes
+pub fn symbols(input: &str) -> impl Iterator<Item = String> + '_ {
+    GLOBAL_LABELS.captures_iter(input).filter_map(|cap| {
+        Some(format!(
+            "{:#}",
+            rustc_demangle::try_demangle(cap.get(1)?.as_str()).ok()?
+        ))
+    })
+}
+
 #[cfg(test)]
 mod test {
     use owo_colors::set_override;
 
-    use super::{contents, name};
+    use super::{contents, local_label_names, name, symbols};
     const MAC: &str =
         "__ZN58_$LT$nom..error..ErrorKind$u20$as$u20$core..fmt..Debug$GT$3fmt17hb98704099c11c31fE";
     const LINUX: &str =
@@ -166,6 +183,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn local_labels_in_args() {
+        let x = local_label_names("w0, .LBB0_3").collect::<Vec<_>>();
+        assert_eq!(x, [".LBB0_3"]);
+        let x = local_label_names("xmm0, xmmword ptr [rip + .LCPI3_0]").collect::<Vec<_>>();
+        assert_eq!(x, [".LCPI3_0"]);
+    }
+
+    #[test]
+    fn symbols_in_args() {
+        let x = symbols(CALL_M).collect::<Vec<_>>();
+        assert_eq!(x, ["<nom::error::ErrorKind as core::fmt::Debug>::fmt"]);
+        let x = symbols("qword ptr [rip + .LCPI0_1]").collect::<Vec<_>>();
+        assert!(x.is_empty());
+    }
+
     #[test]
     fn mac_demangle_call2() {
         set_override(true);
@@ -0,0 +1,239 @@
+//! Line based diff for comparing generated code
+//!
+//! Label numbers and hashes change between unrelated builds so both sides
+//! are normalized with [`normalize`] before comparing.
+use crate::{color, demangle};
+use once_cell::sync::Lazy;
+use owo_colors::OwoColorize;
+use regex::Regex;
+use std::{collections::BTreeMap, io::Write};
+
+static HASHES: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"::h[0-9a-f]{16}\b").expect("regexp should be valid"));
+
+static COLORS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").expect("regexp should be valid"));
+
+/// Replace local label numbers and symbol hashes with stable placeholders
+///
+/// Labels are renamed in order of appearance keeping their alphabetic prefix
+/// so `.LBB5_12` becomes something like `.LBB_0`, hashes become `::h<hash>`.
+/// Colors are removed so code rendered for the terminal can be compared as is
+#[must_use]
+pub fn normalize(input: &str) -> String {
+    let input = strip_colors(input);
+    let mut labels = BTreeMap::new();
+    let mut res = String::with_capacity(input.len());
+    for line in input.lines() {
+        let mut prev = 0;
+        for m in demangle::local_labels(line) {
+            // regex can capture a leading separator, label itself is what's left
+            let start = m.start() + m.as_str().find(['.', 'L']).unwrap_or(0);
+            let label = &line[start..m.end()];
+            let next = labels.len();
+            let ix = *labels.entry(label).or_insert(next);
+            let prefix = label.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+            res.push_str(&line[prev..start]);
+            res.push_str(&format!("{prefix}_{ix}"));
+            prev = m.end();
+        }
+        res.push_str(&line[prev..]);
+        res.push('\n');
+    }
+    HASHES.replace_all(&res, "::h<hash>").into_owned()
+}
+
+/// Remove escape sequences used for colors
+#[must_use]
+pub fn strip_colors(input: &str) -> std::borrow::Cow<'_, str> {
+    COLORS.replace_all(input, "")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Shortest edit script between `a` and `b`, Myers' algorithm
+#[must_use]
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<Change<'a>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    let ix = |k: isize| (k + max + 1) as usize;
+
+    // furthest reaching x for each diagonal k, backtracking needs a snapshot of diagonals
+    // -d-1..=d+1 for each edit distance d
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace = Vec::new();
+    'outer: for d in 0..=max {
+        trace.push(v[ix(-d - 1)..=ix(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[ix(k - 1)] < v[ix(k + 1)]) {
+                v[ix(k + 1)]
+            } else {
+                v[ix(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[ix(k)] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+
+    let mut res = Vec::with_capacity(a.len().max(b.len()));
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            res.push(Change::Same(a[x as usize]));
+        }
+        if d > 0 {
+            if x == prev_x {
+                res.push(Change::Added(b[prev_y as usize]));
+            } else {
+                res.push(Change::Removed(a[prev_x as usize]));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    res.reverse();
+    res
+}
+
+/// Write changes in unified diff format with `context` lines around each hunk
+///
+/// Returns `false` if there are no changes
+pub fn write_unified(
+    writer: &mut impl Write,
+    changes: &[Change],
+    context: usize,
+) -> std::io::Result<bool> {
+    // positions of changes that are close enough to end up in the same hunk get grouped
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (pos, change) in changes.iter().enumerate() {
+        if matches!(change, Change::Same(_)) {
+            continue;
+        }
+        let start = pos.saturating_sub(context);
+        let end = (pos + context + 1).min(changes.len());
+        match hunks.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    // line numbers in both files for each position in `changes`
+    let mut a_line = 1;
+    let mut b_line = 1;
+    let mut lines = Vec::with_capacity(changes.len());
+    for change in changes {
+        lines.push((a_line, b_line));
+        match change {
+            Change::Same(_) => {
+                a_line += 1;
+                b_line += 1;
+            }
+            Change::Removed(_) => a_line += 1,
+            Change::Added(_) => b_line += 1,
+        }
+    }
+
+    for &(start, end) in &hunks {
+        let hunk = &changes[start..end];
+        let a_len = hunk
+            .iter()
+            .filter(|c| !matches!(c, Change::Added(_)))
+            .count();
+        let b_len = hunk
+            .iter()
+            .filter(|c| !matches!(c, Change::Removed(_)))
+            .count();
+        let (a_start, b_start) = lines[start];
+        let header = format!("@@ -{a_start},{a_len} +{b_start},{b_len} @@");
+        writeln!(writer, "{}", color!(header, OwoColorize::cyan))?;
+        for change in hunk {
+            match change {
+                Change::Same(line) => writeln!(writer, " {line}")?,
+                Change::Removed(line) => {
+                    writeln!(writer, "{}", color!(format!("-{line}"), OwoColorize::red))?;
+                }
+                Change::Added(line) => {
+                    writeln!(writer, "{}", color!(format!("+{line}"), OwoColorize::green))?;
+                }
+            }
+        }
+    }
+    Ok(!hunks.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff_lines, normalize, write_unified, Change};
+
+    #[test]
+    fn normalize_labels_and_hashes() {
+        let a = "\tjne\t.LBB5_12\n.LBB5_12:\n\tcall\tfoo::bar::h0123456789abcdef\n\tjmp\t.LBB5_3\n";
+        let b = "\tjne\t.LBB7_2\n.LBB7_2:\n\tcall\tfoo::bar::hfedcba9876543210\n\tjmp\t.LBB7_9\n";
+        assert_eq!(normalize(a), normalize(b));
+        assert_eq!(
+            normalize(a),
+            "\tjne\t.LBB_0\n.LBB_0:\n\tcall\tfoo::bar::h<hash>\n\tjmp\t.LBB_1\n"
+        );
+        let colored = "\tjne\t\u{1b}[90m.LBB5_12\u{1b}[39m\n\u{1b}[90m.LBB5_12\u{1b}[39m:\n\tcall\t\u{1b}[32mfoo::bar::h0123456789abcdef\u{1b}[39m\n\tjmp\t.LBB5_3\n";
+        assert_eq!(normalize(colored), normalize(a));
+    }
+
+    #[test]
+    fn simple_diff() {
+        let a = ["a", "b", "c", "d"];
+        let b = ["a", "c", "d", "e"];
+        let changes = diff_lines(&a, &b);
+        assert_eq!(
+            changes,
+            [
+                Change::Same("a"),
+                Change::Removed("b"),
+                Change::Same("c"),
+                Change::Same("d"),
+                Change::Added("e"),
+            ]
+        );
+
+        // other tests toggle colors, only check the uncolored parts
+        let mut out = Vec::new();
+        assert!(write_unified(&mut out, &changes, 1).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        for part in ["@@ -1,4 +1,4 @@", "\n a\n", "-b", "\n c\n d\n", "+e"] {
+            assert!(out.contains(part), "{part:?} is missing in {out:?}");
+        }
+    }
+
+    #[test]
+    fn no_changes() {
+        let a = ["a", "b"];
+        let changes = diff_lines(&a, &a);
+        assert!(!write_unified(&mut Vec::new(), &changes, 3).unwrap());
+        assert!(diff_lines(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,40 @@
+//! Machine code disassembled from object files, rlibs, static libraries and executables
+//!
+//! Functions are disassembled into text shaped like assembly emitted by rustc so everything
+//! that works with assembly works here too: every function gets its own section, jump
+//! targets get local labels and every instruction is followed by its address and bytes
+use std::path::Path;
+
+use crate::{
+    asm,
+    opts::{Format, ToDump},
+};
+
+#[cfg(feature = "disasm")]
+mod objects;
+
+#[cfg(feature = "disasm")]
+pub use objects::disassemble;
+
+/// Disassemble functions from an object file, an archive or an executable
+///
+/// # Errors
+/// Always, cargo-show-asm was built without a disassembler
+#[cfg(not(feature = "disasm"))]
+pub fn disassemble(_path: &Path) -> anyhow::Result<String> {
+    anyhow::bail!("cargo-show-asm was built without the disassembler, install it with `-F disasm`")
+}
+
+/// Dump `goal` from disassembled machine code
+///
+/// # Errors
+/// Reports file IO errors and files that can't be disassembled
+pub fn dump_function(
+    goal: ToDump,
+    path: &Path,
+    sysroot: &Path,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let contents = disassemble(path)?;
+    asm::dump_contents(goal, &contents, sysroot, fmt)
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+};
+
+use anyhow::Context;
+use capstone::{
+    arch::{self, BuildsCapstone, BuildsCapstoneSyntax},
+    Capstone, Insn, InsnGroupType,
+};
+use object::{
+    read::archive::ArchiveFile, Architecture, Object, ObjectKind, ObjectSection, ObjectSymbol,
+    RelocationTarget, SectionIndex, SymbolKind,
+};
+
+/// A function symbol and its code
+struct Function<'a> {
+    name: &'a str,
+    global: bool,
+    address: u64,
+    bytes: &'a [u8],
+}
+
+/// Disassemble functions from an object file, an archive or an executable
+///
+/// Members of archives that are not object files, such as rlib metadata, are skipped
+///
+/// # Errors
+/// Reports file IO errors, unsupported architectures and file formats
+pub fn disassemble(path: &Path) -> anyhow::Result<String> {
+    let data = std::fs::read(path)?;
+    let mut out = String::new();
+    let mut count = 0;
+    if let Ok(archive) = ArchiveFile::parse(&*data) {
+        for member in archive.members() {
+            let member = member?;
+            if let Ok(file) = object::File::parse(member.data(&*data)?) {
+                disassemble_object(&file, &mut out, &mut count)?;
+            }
+        }
+    } else {
+        let file = object::File::parse(&*data)
+            .with_context(|| format!("Can't read {} as an object file", path.display()))?;
+        disassemble_object(&file, &mut out, &mut count)?;
+    }
+    if count == 0 {
+        anyhow::bail!("No functions found in {}", path.display());
+    }
+    Ok(out)
+}
+
+fn capstone(arch: Architecture) -> anyhow::Result<Capstone> {
+    let cs = match arch {
+        Architecture::X86_64 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode64)
+            .syntax(arch::x86::ArchSyntax::Intel)
+            .detail(true)
+            .build(),
+        Architecture::I386 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode32)
+            .syntax(arch::x86::ArchSyntax::Intel)
+            .detail(true)
+            .build(),
+        Architecture::Aarch64 => Capstone::new()
+            .arm64()
+            .mode(arch::arm64::ArchMode::Arm)
+            .detail(true)
+            .build(),
+        arch => anyhow::bail!("Disassembling {arch:?} code is not supported"),
+    };
+    cs.map_err(|err| anyhow::anyhow!("Can't create a disassembler: {err}"))
+}
+
+/// Disassemble all the functions from `file`, `count` is the number of functions seen so far
+fn disassemble_object(
+    file: &object::File,
+    out: &mut String,
+    count: &mut usize,
+) -> anyhow::Result<()> {
+    let mut symbols = file
+        .symbols()
+        .filter(|s| s.kind() == SymbolKind::Text && s.is_definition())
+        .filter_map(|s| Some((s.section_index()?, s.address(), s)))
+        .collect::<Vec<_>>();
+    if symbols.is_empty() {
+        return Ok(());
+    }
+    symbols.sort_by_key(|(section, address, _)| (section.0, *address));
+    let cs = capstone(file.architecture())?;
+    // LLVM uses `//` for comments on aarch64, `#` is for immediate values there
+    let comment = if file.architecture() == Architecture::Aarch64 {
+        "//"
+    } else {
+        "#"
+    };
+
+    // Call targets in linked code are addresses, in object files they are relocations
+    let linked = matches!(file.kind(), ObjectKind::Executable | ObjectKind::Dynamic);
+    let names = symbols
+        .iter()
+        .filter(|_| linked)
+        .filter_map(|(_, address, s)| Some((*address, s.name().ok()?)))
+        .collect::<BTreeMap<_, _>>();
+    let mut relocations = BTreeMap::new();
+
+    for (ix, (section_index, address, symbol)) in symbols.iter().enumerate() {
+        let Ok(name) = symbol.name() else { continue };
+        if name.is_empty() {
+            continue;
+        }
+        let section = file.section_by_index(*section_index)?;
+        let data = section.data()?;
+        let start = usize::try_from(address - section.address())?;
+        // Mach-O symbols have no sizes, they end where the next one starts
+        let end = match symbols.get(ix + 1) {
+            _ if symbol.size() > 0 => start + usize::try_from(symbol.size())?,
+            Some((next, next_address, _)) if next == section_index => {
+                usize::try_from(next_address - section.address())?
+            }
+            _ => data.len(),
+        };
+        let Some(bytes) = data.get(start..end) else {
+            continue;
+        };
+        let relocations = relocations
+            .entry(section_index.0)
+            .or_insert_with(|| section_relocations(file, *section_index));
+        let function = Function {
+            name,
+            global: symbol.is_global(),
+            address: *address,
+            bytes,
+        };
+        let offset = section.address();
+        write_function(out, &cs, &function, *count, &names, comment, |addr| {
+            relocations
+                .range(addr - offset..)
+                .next()
+                .map(|(o, name)| (o + offset, name.as_str()))
+        })?;
+        *count += 1;
+    }
+    Ok(())
+}
+
+/// Names of symbols relocations in a section point to, by offset from the section start
+fn section_relocations(file: &object::File, index: SectionIndex) -> BTreeMap<u64, String> {
+    let Ok(section) = file.section_by_index(index) else {
+        return BTreeMap::new();
+    };
+    section
+        .relocations()
+        .filter_map(|(offset, reloc)| {
+            let RelocationTarget::Symbol(symbol) = reloc.target() else {
+                return None;
+            };
+            let symbol = file.symbol_by_index(symbol).ok()?;
+            let name = match symbol.name() {
+                Ok(name) if !name.is_empty() => name,
+                // relocations against sections, rustc puts every function and constant into
+                // a section named after it
+                _ => {
+                    let section = file.section_by_index(symbol.section_index()?).ok()?;
+                    let name = section.name().ok()?;
+                    [".text.", ".rodata.", ".data.rel.ro.", ".data.", ".bss."]
+                        .iter()
+                        .find_map(|prefix| name.strip_prefix(prefix))
+                        .unwrap_or(name)
+                }
+            };
+            Some((offset, name.to_owned()))
+        })
+        .collect()
+}
+
+fn in_group(cs: &Capstone, insn: &Insn, group: InsnGroupType::Type) -> bool {
+    cs.insn_detail(insn)
+        .is_ok_and(|detail| detail.groups().any(|g| u32::from(g.0) == group))
+}
+
+/// Address in the last operand of a jump or a call: `0x1f`, `#0x1f` or `w0, #0x1f`,
+/// capstone prints addresses below 10 in decimal
+fn target_address(op_str: &str) -> Option<(&str, u64)> {
+    let (prefix, operand) = match op_str.rsplit_once(", ") {
+        Some((prefix, operand)) => (&op_str[..=prefix.len() + 1], operand),
+        None => ("", op_str),
+    };
+    let operand = operand.trim_start_matches('#');
+    let address = match operand.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => operand.parse().ok()?,
+    };
+    Some((prefix, address))
+}
+
+/// Write a single function, `count` makes local labels unique
+///
+/// Jumps inside of the function point to local labels, calls and jumps to other functions
+/// point to their names, either from `names` by address or from `relocation` that returns
+/// the first relocation at or after the address along with its address
+fn write_function<'a>(
+    out: &mut String,
+    cs: &Capstone,
+    function: &Function,
+    count: usize,
+    names: &BTreeMap<u64, &str>,
+    comment: &str,
+    relocation: impl Fn(u64) -> Option<(u64, &'a str)>,
+) -> anyhow::Result<()> {
+    let Function {
+        name,
+        global,
+        address,
+        bytes,
+    } = function;
+    let range = *address..address + bytes.len() as u64;
+    let insns = cs
+        .disasm_all(bytes, *address)
+        .map_err(|err| anyhow::anyhow!("Can't disassemble {name}: {err}"))?;
+    let is_jump = |insn: &Insn| in_group(cs, insn, InsnGroupType::CS_GRP_JUMP);
+    let is_call = |insn: &Insn| in_group(cs, insn, InsnGroupType::CS_GRP_CALL);
+    // relocation covering bytes of the instruction
+    let reloc = |insn: &Insn| {
+        let end = insn.address() + insn.bytes().len() as u64;
+        relocation(insn.address())
+            .filter(|(at, _)| *at < end)
+            .map(|(_, name)| name)
+    };
+    let targets = insns
+        .iter()
+        .filter(|insn| is_jump(insn) && reloc(insn).is_none())
+        .filter_map(|insn| Some(target_address(insn.op_str()?)?.1))
+        .filter(|target| range.contains(target))
+        .collect::<BTreeSet<_>>();
+
+    writeln!(out, "\t.section\t.text.{name},\"ax\",@progbits")?;
+    if *global {
+        writeln!(out, "\t.globl\t{name}")?;
+    }
+    writeln!(out, "\t.type\t{name},@function")?;
+    writeln!(out, "{name}:")?;
+    for insn in insns.iter() {
+        let addr = insn.address();
+        if targets.contains(&addr) {
+            writeln!(out, ".L{count}_{addr:x}:")?;
+        }
+        let reloc = reloc(&insn);
+        let mut op_str = insn.op_str().unwrap_or_default().to_owned();
+        let mut reloc_comment = reloc;
+        if is_jump(&insn) || is_call(&insn) {
+            if let Some((prefix, target)) = target_address(&op_str) {
+                // relocations come first, unrelocated target in object files is meaningless
+                let label = match reloc {
+                    Some(reloc) => Some(reloc.to_owned()),
+                    None if targets.contains(&target) => Some(format!(".L{count}_{target:x}")),
+                    None => names.get(&target).map(|name| (*name).to_owned()),
+                };
+                if let Some(label) = label {
+                    op_str = format!("{prefix}{label}");
+                    reloc_comment = None;
+                }
+            }
+        }
+
+        let mnemonic = insn.mnemonic().unwrap_or("?");
+        if op_str.is_empty() {
+            write!(out, "\t{mnemonic}")?;
+        } else {
+            write!(out, "\t{mnemonic}\t{op_str}")?;
+        }
+        write!(out, "\t{comment} {addr:x}:")?;
+        for byte in insn.bytes() {
+            write!(out, " {byte:02x}")?;
+        }
+        if let Some(reloc) = reloc_comment {
+            write!(out, " <{reloc}>")?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, ".Lfunc_end{count}:")?;
+    writeln!(out, "\t.size\t{name}, {}", bytes.len())?;
+    Ok(())
+}
+
+#[test]
+fn test_write_function() {
+    let cs = capstone(Architecture::X86_64).unwrap();
+    // test edi, edi; je 1f; call foo; 1: ret
+    let bytes = [0x85, 0xff, 0x74, 0x05, 0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3];
+    let function = Function {
+        name: "_ZN4test3bar17h0123456789abcdefE",
+        global: true,
+        address: 0,
+        bytes: &bytes,
+    };
+    let mut out = String::new();
+    let relocation = |addr: u64| (addr <= 5).then_some((5, "_ZN4test3foo17h0123456789abcdefE"));
+    write_function(
+        &mut out,
+        &cs,
+        &function,
+        3,
+        &BTreeMap::new(),
+        "#",
+        relocation,
+    )
+    .unwrap();
+    assert_eq!(
+        out,
+        "\t.section\t.text._ZN4test3bar17h0123456789abcdefE,\"ax\",@progbits
+\t.globl\t_ZN4test3bar17h0123456789abcdefE
+\t.type\t_ZN4test3bar17h0123456789abcdefE,@function
+_ZN4test3bar17h0123456789abcdefE:
+\ttest\tedi, edi\t# 0: 85 ff
+\tje\t.L3_9\t# 2: 74 05
+\tcall\t_ZN4test3foo17h0123456789abcdefE\t# 4: e8 00 00 00 00
+.L3_9:
+\tret\t# 9: c3
+.Lfunc_end3:
+\t.size\t_ZN4test3bar17h0123456789abcdefE, 10
+"
+    );
+}
@@ -44,7 +44,7 @@ fn get_socket() -> LocalSocketListener {
     match LocalSocketListener::bind(address.clone()) {
         Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
             esafeprintln!("Error: socket {} is already in use", address);
-            std::process::exit(1);
+            crate::exit(1);
         }
         x => x.expect("Unexpected Socket error"),
     }
@@ -179,6 +179,11 @@ fn ping_pong_test() {
 
             Ok(())
         }
+
+        fn range_to_json(&self, range: Option<Range<usize>>) -> anyhow::Result<serde_json::Value> {
+            let lines = range.map_or(self.data.as_slice(), |r| &self.data[r]);
+            Ok(serde_json::json!({ "lines": lines }))
+        }
     }
 
     let file = "First\n\
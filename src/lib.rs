@@ -1,18 +1,23 @@
 #![doc = include_str!("../README.md")]
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     io::{self, Write},
-    ops::Range,
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
 use anyhow::Context;
-use opts::{Format, ToDump};
+use opts::{Format, Syntax, ToDump};
 use select::{Finder, SelectProcess};
 
 pub mod asm;
 pub mod cached_lines;
+pub mod cgu;
 pub mod demangle;
+pub mod diff;
+pub mod disasm;
 
 #[cfg(feature = "ipc")]
 pub mod ipc;
@@ -20,6 +25,7 @@ pub mod llvm;
 pub mod mca;
 pub mod mir;
 pub mod opts;
+pub mod report;
 pub mod select;
 
 #[macro_export]
@@ -40,7 +46,7 @@ macro_rules! safeprintln {
     ($($x:expr),* $(,)?) => {{
         use std::io::Write;
         if writeln!(std::io::stdout(), $($x),*).is_err() {
-            std::process::exit(0);
+            $crate::exit(0);
         }
     }};
 }
@@ -50,7 +56,7 @@ macro_rules! safeprint {
     ($($x:expr),* $(,)?) => {{
         use std::io::Write;
         if write!(std::io::stdout(), $($x),*).is_err() {
-            std::process::exit(0);
+            $crate::exit(0);
         }
     }};
 }
@@ -60,7 +66,7 @@ macro_rules! esafeprintln {
     ($($x:expr),* $(,)?) => {{
         use std::io::Write;
         if writeln!(std::io::stderr(), $($x),*).is_err() {
-            std::process::exit(0);
+            $crate::exit(0);
         }
     }};
 }
@@ -70,7 +76,7 @@ macro_rules! esafeprint {
     ($($x:expr),* $(,)?) => {{
         use std::io::Write;
         if write!(std::io::stderr(), $($x),*).is_err() {
-            std::process::exit(0);
+            $crate::exit(0);
         }
     }};
 }
@@ -122,7 +128,61 @@ pub fn suggest_name<'a>(search: &str, full: bool, items: impl IntoIterator<Item
         ix += lens.len();
     }
 
-    std::process::exit(1);
+    exit(1);
+}
+
+/// Check if item name matches the FUNCTION passed on the command line
+///
+/// By default FUNCTION can be any part of the name, `regex` treats it as a regular expression
+/// and `exact` requires it to match the whole name, hash is allowed but not required.
+/// Prints a message and exits if `function` is not a valid regular expression
+fn name_matcher(function: &str, regex: bool, exact: bool) -> impl Fn(&Item) -> bool {
+    let pattern = if regex {
+        function.to_owned()
+    } else {
+        regex::escape(function)
+    };
+    let pattern = if exact {
+        format!("^(?:{pattern})$")
+    } else {
+        pattern
+    };
+    let re = regex::Regex::new(&pattern).unwrap_or_else(|err| {
+        esafeprintln!("{function:?} is not a valid regular expression: {err}");
+        exit(1);
+    });
+    move |item: &Item| re.is_match(&item.name) || (exact && re.is_match(&item.hashed))
+}
+
+/// Pick all the items matching a goal when `--all-matches` is used
+///
+/// Returns `None` for other goals, prints a message and exits if nothing matches
+#[must_use]
+pub fn get_all_matches(
+    goal: &ToDump,
+    items: &BTreeMap<Item, Range<usize>>,
+) -> Option<Vec<(Item, Range<usize>)>> {
+    let ToDump::Function {
+        function,
+        regex,
+        exact,
+        all_matches: true,
+        ..
+    } = goal
+    else {
+        return None;
+    };
+    let matcher = name_matcher(function, *regex, *exact);
+    let matches = items
+        .iter()
+        .filter(|(item, _range)| matcher(item))
+        .map(|(item, range)| (item.clone(), range.clone()))
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        safeprintln!("Can't find any items matching {function:?}");
+        exit(1);
+    }
+    Some(matches)
 }
 
 /// Pick an item to dump based on a goal
@@ -133,14 +193,13 @@ pub fn get_dump_range(
     goal: ToDump,
     fmt: &Format,
     items: BTreeMap<Item, Range<usize>>,
-) -> Option<Range<usize>> {
+) -> Option<(Item, Range<usize>)> {
     if items.len() == 1 {
         return Some(
             items
                 .into_iter()
                 .next()
-                .expect("We just checked there's one item present")
-                .1,
+                .expect("We just checked there's one item present"),
         );
     }
 
@@ -150,51 +209,60 @@ pub fn get_dump_range(
 
         // By index without filtering
         ToDump::ByIndex { value } => {
-            if let Some(range) = items.values().nth(value) {
-                Some(range.clone())
+            let actual = items.len();
+            if let Some(item) = items.into_iter().nth(value) {
+                Some(item)
             } else {
-                let actual = items.len();
                 safeprintln!(
                 "You asked to display item #{value} (zero based), but there's only {actual} items"
             );
-                std::process::exit(1);
+                exit(1);
             }
         }
 
         // By index with filtering
-        ToDump::Function { function, nth } => {
+        ToDump::Function {
+            function,
+            nth,
+            regex,
+            exact,
+            ..
+        } => {
+            let matcher = name_matcher(&function, regex, exact);
             let filtered = items
                 .iter()
-                .filter(|(item, _range)| item.name.contains(&function))
+                .filter(|(item, _range)| matcher(item))
                 .collect::<Vec<_>>();
 
-            let range = if nth.is_none() && filtered.len() == 1 {
+            let (item, range) = if nth.is_none() && filtered.len() == 1 {
                 filtered
-                    .get(0)
+                    .first()
                     .expect("Must have one item as checked above")
-                    .1
-                    .clone()
             } else if let Some(range) = nth.and_then(|nth| filtered.get(nth)) {
-                range.1.clone()
+                range
             } else if let Some(value) = nth {
                 let filtered = filtered.len();
                 safeprintln!("You asked to display item #{value} (zero based), but there's only {filtered} matching items");
-                std::process::exit(1);
+                exit(1);
             } else {
                 if filtered.is_empty() {
                     safeprintln!("Can't find any items matching {function:?}");
                 } else {
                     suggest_name(&function, fmt.full_name, filtered.iter().map(|x| x.0));
                 }
-                std::process::exit(1);
+                exit(1);
             };
-            Some(range)
+            Some(((*item).clone(), (*range).clone()))
         }
 
         ToDump::Interactive => {
             panic!("Interactive Mode should already be checked")
         }
 
+        ToDump::Report { .. } => {
+            panic!("Reports should already be handled")
+        }
+
         // Unspecified, so print suggestions and exit
         ToDump::Unspecified => {
             let items = items.into_keys().collect::<Vec<_>>();
@@ -204,11 +272,99 @@ pub fn get_dump_range(
     }
 }
 
+/// Source file the user refers to, relative `src/lib.rs` matches any file ending with those
+/// components
+#[must_use]
+pub fn is_source_file(full: &Path, path: &Path) -> bool {
+    full.ends_with(path) || path.canonicalize().is_ok_and(|c| c == full)
+}
+
+/// Items with code generated from `line`, inlined copies included, `lines` gives source lines
+/// a range of the file has code from
+///
+/// Items with code pointing exactly at the line are preferred, if there are none - items
+/// spanning over the line are picked instead, this helps with lines that don't produce any
+/// code by themselves such as a function signature or a comment
+pub fn items_for_line<I>(
+    items: &BTreeMap<Item, Range<usize>>,
+    line: u64,
+    mut lines: impl FnMut(Range<usize>) -> I,
+) -> Vec<&Item>
+where
+    I: IntoIterator<Item = u64>,
+{
+    let mut exact = Vec::new();
+    let mut spanning = Vec::new();
+    for (item, range) in items {
+        let mut span: Option<(u64, u64)> = None;
+        let mut hit = false;
+        for l in lines(range.clone()) {
+            hit |= l == line;
+            span = Some(span.map_or((l, l), |(lo, hi)| (lo.min(l), hi.max(l))));
+        }
+        if hit {
+            exact.push(item);
+        } else if span.is_some_and(|(lo, hi)| (lo..=hi).contains(&line)) {
+            spanning.push(item);
+        }
+    }
+    if exact.is_empty() {
+        spanning
+    } else {
+        exact
+    }
+}
+
+/// Narrow down items to ones with code from a source location passed instead of a name
+///
+/// Other goals are kept as is. Matching items are selected with an empty name filter so
+/// INDEX, `--all-matches` and suggestions work the same way as with a name. `for_line` finds
+/// items with code from a line of a file
+#[must_use]
+pub fn select_by_location(
+    goal: ToDump,
+    items: BTreeMap<Item, Range<usize>>,
+    for_line: impl FnOnce(&BTreeMap<Item, Range<usize>>, &Path, u64) -> BTreeSet<Item>,
+) -> (ToDump, BTreeMap<Item, Range<usize>>) {
+    let Some((path, line)) = goal.source_location() else {
+        return (goal, items);
+    };
+    let selected = for_line(&items, path, line);
+    if selected.is_empty() {
+        safeprintln!(
+            "Can't find any code generated from line {line} of {}",
+            path.display()
+        );
+        exit(1);
+    }
+    let items = items
+        .into_iter()
+        .filter(|(item, _)| selected.contains(item))
+        .collect();
+    let goal = match goal {
+        ToDump::Function {
+            with_callees,
+            all_matches,
+            nth,
+            ..
+        } => ToDump::Function {
+            with_callees,
+            regex: false,
+            exact: false,
+            all_matches,
+            function: String::new(),
+            nth,
+        },
+        goal => goal,
+    };
+    (goal, items)
+}
+
 pub trait DumpRange {
     fn dump_range(&self, range: Option<Range<usize>>) -> anyhow::Result<()> {
         let mut writer = io::stdout();
         if self.dump_range_into_writer(range, &mut writer).is_err() || writer.flush().is_err() {
-            std::process::exit(0); // Exit when stdout is closed
+            exit(0); // Exit when stdout is closed
         }
         Ok(())
     }
@@ -218,6 +374,240 @@ pub trait DumpRange {
         range: Option<Range<usize>>,
         writer: &mut impl Write,
     ) -> anyhow::Result<()>;
+
+    /// Machine readable representation of a range, used by `--json`
+    ///
+    /// Returns an object with contents of the range, item description is added by [`dump_json`]
+    fn range_to_json(&self, range: Option<Range<usize>>) -> anyhow::Result<serde_json::Value>;
+
+    /// Dump items picked by [`get_all_matches`] one after another, each one with a header
+    ///
+    /// JSON output contains an array of items instead
+    fn dump_items(&self, fmt: &Format, items: Vec<(Item, Range<usize>)>) -> anyhow::Result<()> {
+        if fmt.json {
+            let items = items
+                .into_iter()
+                .map(|(item, range)| {
+                    let mut body = self.range_to_json(Some(range))?;
+                    body["item"] = item.to_json();
+                    Ok(body)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            return dump_json(None, serde_json::json!({ "items": items }));
+        }
+
+        for (ix, (item, range)) in items.into_iter().enumerate() {
+            if ix > 0 {
+                safeprintln!();
+            }
+            let name = if fmt.full_name {
+                &item.hashed
+            } else {
+                &item.name
+            };
+            let header = format!("==> {name} <==");
+            safeprintln!("{}", color!(header, owo_colors::OwoColorize::bright_black));
+            self.dump_range(Some(range))?;
+        }
+        Ok(())
+    }
+
+    /// Dump an item picked by [`get_dump_range`] as text or as JSON, depending on `fmt`
+    ///
+    /// With `--check` item is compared against a snapshot instead
+    fn dump_item(&self, fmt: &Format, item: Option<(Item, Range<usize>)>) -> anyhow::Result<()> {
+        if let Some(path) = &fmt.check {
+            return check_snapshot(self, fmt, path, item.map(|i| i.1));
+        }
+        match item {
+            Some((item, range)) if fmt.json => {
+                dump_json(Some(&item), self.range_to_json(Some(range))?)
+            }
+            None if fmt.json => dump_json(None, self.range_to_json(None)?),
+            item => self.dump_range(item.map(|i| i.1)),
+        }
+    }
+}
+
+impl Item {
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "hashed": self.hashed,
+            "index": self.index,
+            "len": self.len,
+        })
+    }
+}
+
+/// Compare a rendered range against a snapshot stored in `path`, update it with `--bless`
+///
+/// Labels and hashes are normalized so only changes in the generated code matter,
+/// prints the difference and exits with an error if snapshot doesn't match
+fn check_snapshot<D: DumpRange + ?Sized>(
+    dump_ctx: &D,
+    fmt: &Format,
+    path: &Path,
+    range: Option<Range<usize>>,
+) -> anyhow::Result<()> {
+    let mut buf = Vec::new();
+    dump_ctx.dump_range_into_writer(range, &mut buf)?;
+    let current = diff::normalize(&String::from_utf8(buf)?);
+
+    match compare_snapshot(&current, path, fmt.bless)? {
+        Snapshot::Matches => Ok(()),
+        Snapshot::Updated => {
+            esafeprintln!("Updated snapshot {}", path.display());
+            Ok(())
+        }
+        Snapshot::Missing => {
+            esafeprintln!(
+                "Can't read snapshot {}, use --bless to create it",
+                path.display()
+            );
+            exit(1);
+        }
+        Snapshot::Differs(expected) => {
+            let expected = expected.lines().collect::<Vec<_>>();
+            let current = current.lines().collect::<Vec<_>>();
+            let changes = diff::diff_lines(&expected, &current);
+            safeprintln!("Generated code doesn't match snapshot {}", path.display());
+            let mut writer = io::stdout();
+            if diff::write_unified(&mut writer, &changes, 3).is_err() || writer.flush().is_err() {
+                exit(0); // Exit when stdout is closed
+            }
+            exit(1);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Snapshot {
+    Matches,
+    /// Snapshot was written with `--bless`
+    Updated,
+    Missing,
+    /// Normalized contents of the snapshot
+    Differs(String),
+}
+
+/// Compare normalized code against a snapshot in `path` or replace the snapshot with it
+fn compare_snapshot(current: &str, path: &Path, bless: bool) -> anyhow::Result<Snapshot> {
+    if bless {
+        std::fs::write(path, current)
+            .with_context(|| format!("Failed to write snapshot to {}", path.display()))?;
+        return Ok(Snapshot::Updated);
+    }
+    let Ok(expected) = std::fs::read_to_string(path) else {
+        return Ok(Snapshot::Missing);
+    };
+    let expected = diff::normalize(&expected);
+    Ok(if expected == current {
+        Snapshot::Matches
+    } else {
+        Snapshot::Differs(expected)
+    })
+}
+
+/// Temporary files and directories that are still around, [`exit`] removes them
+static TEMP_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Temporary file or directory, removed when dropped or when the process stops with [`exit`]
+#[derive(Debug)]
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    /// Start tracking `path`, it doesn't have to exist yet
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        TEMP_PATHS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(path.clone());
+        Self(path)
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        remove_temp_path(&self.0);
+        TEMP_PATHS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|path| path != &self.0);
+    }
+}
+
+fn remove_temp_path(path: &Path) {
+    let _ = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+}
+
+/// Remove all the [`TempPath`] files and exit, [`std::process::exit`] doesn't run destructors
+pub fn exit(code: i32) -> ! {
+    let paths = std::mem::take(&mut *TEMP_PATHS.lock().unwrap_or_else(|e| e.into_inner()));
+    for path in paths {
+        remove_temp_path(&path);
+    }
+    std::process::exit(code)
+}
+
+/// Save `input` to a temporary file with the extension `syntax` uses, `--file -` reads stdin
+/// this way since all the dumpers work with files
+///
+/// The file is removed once the result is dropped
+///
+/// # Errors
+/// Reports IO errors
+pub fn save_input(mut input: impl io::Read, syntax: Syntax) -> anyhow::Result<TempPath> {
+    // object files are binary
+    let mut contents = Vec::new();
+    input.read_to_end(&mut contents)?;
+    let path = TempPath::new(std::env::temp_dir().join(format!(
+        "cargo-show-asm-{}.{}",
+        std::process::id(),
+        syntax.ext()
+    )));
+    std::fs::write(&*path, contents)?;
+    Ok(path)
+}
+
+/// Print JSON `body` with item description attached to stdout
+///
+/// `item` is `None` when dumping the whole file
+pub fn dump_json(item: Option<&Item>, body: serde_json::Value) -> anyhow::Result<()> {
+    let mut writer = io::stdout();
+    if write_json(&mut writer, item, body).is_err() || writer.flush().is_err() {
+        exit(0); // Exit when stdout is closed
+    }
+    Ok(())
+}
+
+fn write_json(
+    writer: &mut impl Write,
+    item: Option<&Item>,
+    mut body: serde_json::Value,
+) -> io::Result<()> {
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert(
+            "item".to_owned(),
+            item.map_or(serde_json::Value::Null, Item::to_json),
+        );
+    }
+    serde_json::to_writer_pretty(&mut *writer, &body)?;
+    writeln!(writer)
 }
 
 pub fn interactive_mode(
@@ -226,7 +616,7 @@ pub fn interactive_mode(
 ) {
     let finder = Finder::in_path_suggestion().unwrap_or_else(|| {
         esafeprintln!("No finder found in PATH");
-        std::process::exit(1);
+        exit(1);
     });
     let mut selector = SelectProcess::default_command(finder);
 
@@ -265,7 +655,7 @@ pub fn interactive_mode(
 
     if !selector_out.status.success() {
         esafeprintln!("Interactive process aborted");
-        std::process::exit(1);
+        exit(1);
     }
 
     let selected_index =
@@ -276,7 +666,7 @@ pub fn interactive_mode(
         .nth(selected_index)
         .or_else(|| {
             esafeprintln!("Invalid index selected");
-            std::process::exit(1);
+            exit(1);
         })
         .cloned();
 
@@ -284,3 +674,109 @@ pub fn interactive_mode(
         .dump_range(range)
         .expect("Should not fail without corruption");
 }
+
+#[test]
+fn test_name_matcher() {
+    let item = Item {
+        name: "<T as core::iter::Iterator>::next".to_owned(),
+        hashed: "<T as core::iter::Iterator>::next::h0123456789abcdef".to_owned(),
+        index: 0,
+        len: 0,
+    };
+    assert!(name_matcher("Iterator>::n", false, false)(&item));
+    assert!(!name_matcher("Iterator>::n", false, true)(&item));
+    assert!(name_matcher(
+        "<T as core::iter::Iterator>::next",
+        false,
+        true
+    )(&item));
+    assert!(name_matcher(&item.hashed, false, true)(&item));
+    assert!(name_matcher(r"Iterator>::(next|fold)$", true, false)(&item));
+    assert!(!name_matcher(r"Iterator>::fold", true, false)(&item));
+    assert!(!name_matcher(r"Iterator>::(next|fold)", true, true)(&item));
+}
+
+#[test]
+fn test_write_json() {
+    let item = Item {
+        name: "foo::bar".to_owned(),
+        hashed: "foo::bar::h0123456789abcdef".to_owned(),
+        index: 2,
+        len: 10,
+    };
+    let mut out = Vec::new();
+    write_json(
+        &mut out,
+        Some(&item),
+        serde_json::json!({ "lines": ["a", "b"] }),
+    )
+    .unwrap();
+    assert!(out.ends_with(b"}\n"));
+    let value = serde_json::from_slice::<serde_json::Value>(&out).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "lines": ["a", "b"],
+            "item": { "name": "foo::bar", "hashed": "foo::bar::h0123456789abcdef", "index": 2, "len": 10 },
+        })
+    );
+
+    let mut out = Vec::new();
+    write_json(&mut out, None, serde_json::json!({ "lines": [] })).unwrap();
+    let value = serde_json::from_slice::<serde_json::Value>(&out).unwrap();
+    assert_eq!(value, serde_json::json!({ "lines": [], "item": null }));
+}
+
+#[test]
+fn test_compare_snapshot() {
+    let dir = std::env::temp_dir().join(format!("cargo-show-asm-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("foo.s");
+    let code = diff::normalize("foo:\n\tjne\t.LBB0_2\n.LBB0_2:\n\tret\n");
+
+    assert_eq!(
+        compare_snapshot(&code, &path, false).unwrap(),
+        Snapshot::Missing
+    );
+    assert_eq!(
+        compare_snapshot(&code, &path, true).unwrap(),
+        Snapshot::Updated
+    );
+    assert_eq!(
+        compare_snapshot(&code, &path, false).unwrap(),
+        Snapshot::Matches
+    );
+
+    // label numbers don't matter, instructions do
+    let renumbered = diff::normalize("foo:\n\tjne\t.LBB3_7\n.LBB3_7:\n\tret\n");
+    assert_eq!(
+        compare_snapshot(&renumbered, &path, false).unwrap(),
+        Snapshot::Matches
+    );
+    let changed = diff::normalize("foo:\n\tje\t.LBB0_2\n.LBB0_2:\n\tret\n");
+    assert_eq!(
+        compare_snapshot(&changed, &path, false).unwrap(),
+        Snapshot::Differs(code)
+    );
+
+    assert_eq!(
+        compare_snapshot(&changed, &path, true).unwrap(),
+        Snapshot::Updated
+    );
+    assert_eq!(
+        compare_snapshot(&changed, &path, false).unwrap(),
+        Snapshot::Matches
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_save_input() {
+    let input = "foo:\n\tret\n";
+    let path = save_input(input.as_bytes(), Syntax::Llvm).unwrap();
+    let saved = path.to_path_buf();
+    assert_eq!(saved.extension().unwrap(), "ll");
+    assert_eq!(std::fs::read_to_string(&saved).unwrap(), input);
+    drop(path);
+    assert!(!saved.exists());
+}
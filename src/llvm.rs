@@ -1,5 +1,6 @@
 #![allow(clippy::missing_errors_doc)]
 // https://llvm.org/docs/LangRef.html
+use once_cell::sync::Lazy;
 use owo_colors::OwoColorize;
 use regex::Regex;
 
@@ -7,16 +8,16 @@ use crate::{
     cached_lines::CachedLines,
     color,
     demangle::{self, contents},
-    get_dump_range, interactive_mode,
-    opts::{Format, ToDump},
-    safeprintln, DumpRange, Item,
+    get_all_matches, get_dump_range, interactive_mode,
+    opts::{Format, Report, ToDump},
+    report, safeprintln, DumpRange, Item,
 };
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fs::File,
     io::{BufRead, BufReader, Write},
     ops::Range,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug)]
@@ -28,7 +29,8 @@ enum State {
     Define,
 }
 
-fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
+#[must_use]
+pub fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
     let mut res = BTreeMap::new();
     let mut current_item = None::<Item>;
     let regex = Regex::new("@\"?(_?_[a-zA-Z0-9_$.]+)\"?\\(").expect("regexp should be valid");
@@ -68,18 +70,200 @@ fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
     res
 }
 
-pub fn dump_function(goal: ToDump, path: &Path, fmt: &Format) -> anyhow::Result<()> {
+/// Debug locations attached to IR instructions with `!dbg`, resolved to Rust source lines
+struct DebugLocs<'a> {
+    /// metadata nodes by id: `!42 = !DILocation(line: 5, scope: !7)` is stored as `42`
+    nodes: BTreeMap<&'a str, &'a str>,
+    /// paths of `!DIFile` nodes by id, contents once they are loaded
+    files: BTreeMap<&'a str, (PathBuf, Option<CachedLines>)>,
+}
+
+/// Value of a metadata node field, `field("line: 5, column: 3", "line")` gives `5`
+fn field<'a>(node: &'a str, name: &str) -> Option<&'a str> {
+    let start = node.match_indices(name).find_map(|(ix, _)| {
+        let before = node[..ix].chars().next_back();
+        let rest = node[ix + name.len()..].strip_prefix(": ")?;
+        matches!(before, Some('(' | ' ')).then_some(rest)
+    })?;
+    if let Some(quoted) = start.strip_prefix('"') {
+        Some(&quoted[..quoted.find('"')?])
+    } else {
+        let end = start.find([',', ')']).unwrap_or(start.len());
+        Some(&start[..end])
+    }
+}
+
+// debug info is only requested for the sake of Rust annotations, debug records and
+// attachments it adds are noise
+static DBG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r",? !dbg !\d+").expect("regexp should be valid"));
+
+/// `#dbg_value(...)` and similar records or calls to `llvm.dbg.*` intrinsics used in older LLVM
+fn is_debug_record(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("#dbg_") || line.starts_with("call void @llvm.dbg.")
+}
+
+impl<'a> DebugLocs<'a> {
+    fn new(lines: &'a CachedLines) -> Self {
+        let nodes = lines
+            .iter()
+            .filter_map(|line| {
+                let (id, body) = line.strip_prefix('!')?.split_once(" = ")?;
+                id.bytes()
+                    .all(|b| b.is_ascii_digit())
+                    .then_some((id, body.trim_start_matches("distinct ")))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let files = nodes
+            .iter()
+            .filter(|(_, body)| body.starts_with("!DIFile("))
+            .filter_map(|(id, body)| {
+                let name = Path::new(field(body, "filename")?);
+                let path = match field(body, "directory") {
+                    Some(dir) if name.is_relative() => Path::new(dir).join(name),
+                    _ => name.to_owned(),
+                };
+                Some((*id, (path, None)))
+            })
+            .collect();
+        Self { nodes, files }
+    }
+
+    /// Read Rust sources for all the files
+    fn load_sources(&mut self, sysroot: &Path, fmt: &Format) {
+        for (id, (path, lines)) in &mut self.files {
+            if fmt.verbosity > 1 {
+                safeprintln!("Reading file !{id} {}", path.display());
+            }
+            *lines = crate::asm::locate_sources(sysroot, path).and_then(|source| {
+                let sources = std::fs::read_to_string(source).ok()?;
+                Some(CachedLines::without_ending(sources))
+            });
+        }
+    }
+
+    /// Functions with code generated from `line` of `path` according to debug locations
+    fn items_for_line<'i>(
+        &self,
+        strings: &[&str],
+        items: &'i BTreeMap<Item, Range<usize>>,
+        path: &Path,
+        line: u64,
+    ) -> Vec<&'i Item> {
+        let files = &self
+            .files
+            .iter()
+            .filter(|(_, (full, _))| crate::is_source_file(full, path))
+            .map(|(id, _)| *id)
+            .collect::<BTreeSet<_>>();
+        crate::items_for_line(items, line, |range| {
+            strings[range]
+                .iter()
+                .filter_map(|line| self.location(line))
+                .filter(move |(file, _, _)| files.contains(file))
+                .map(|(_, line, _)| line)
+        })
+    }
+
+    /// File id, line and column numbers for an IR instruction with `!dbg` attachment
+    fn location(&self, line: &str) -> Option<(&'a str, u64, u64)> {
+        let (_, dbg) = line.rsplit_once("!dbg !")?;
+        let id = &dbg[..dbg.find(|c: char| !c.is_ascii_digit()).unwrap_or(dbg.len())];
+        let loc = self.nodes.get(id)?;
+        if !loc.starts_with("!DILocation(") {
+            return None;
+        }
+        let line = field(loc, "line")?.parse().ok().filter(|&l| l != 0)?;
+        let column = field(loc, "column")
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(0);
+        // subprograms and lexical blocks all have a file, but just in case
+        let mut scope = field(loc, "scope")?;
+        for _ in 0..32 {
+            let node = self.nodes.get(scope.strip_prefix('!')?)?;
+            if let Some(file) = field(node, "file") {
+                return Some((file.strip_prefix('!')?, line, column));
+            }
+            scope = field(node, "scope")?;
+        }
+        None
+    }
+
+    /// Location in the same shape as `--json` uses for assembly
+    fn loc_to_json(&self, (file_id, line, column): (&str, u64, u64)) -> serde_json::Value {
+        let (file, rust) = match self.files.get(file_id) {
+            Some((fname, lines)) => (
+                Some(fname.display().to_string()),
+                lines
+                    .as_ref()
+                    .and_then(|lines| lines.get((line as usize).checked_sub(1)?)),
+            ),
+            None => (None, None),
+        };
+        serde_json::json!({
+            "file": file,
+            "line": line,
+            "column": column,
+            "rust": rust.map(str::trim_start),
+        })
+    }
+}
+
+pub fn dump_function(
+    goal: ToDump,
+    path: &Path,
+    sysroot: &Path,
+    fmt: &Format,
+) -> anyhow::Result<()> {
     let lines = CachedLines::without_ending(std::fs::read_to_string(path)?);
     let items = find_items(&lines);
+    if let ToDump::Report { report, sort_by } = goal {
+        match report {
+            Report::Sizes => {
+                let sizes = items.iter().map(|(item, range)| {
+                    // instructions are indented, labels, comments and the signature are not
+                    let count = range
+                        .clone()
+                        .filter_map(|ix| lines.get(ix))
+                        .filter(|line| {
+                            line.starts_with("  ") && !line.trim_start().starts_with(';')
+                        })
+                        .count();
+                    (item, count)
+                });
+                return report::dump_sizes(sizes, sort_by, fmt);
+            }
+            Report::Calls => anyhow::bail!("Call graph is only supported for assembly output"),
+            Report::Vtables => anyhow::bail!("Vtables are only supported for assembly output"),
+        }
+    }
     let strs = lines.iter().collect::<Vec<_>>();
+    // debug info is there for Rust annotations or to find functions by source location
+    let mut locs = (fmt.rust || goal.source_location().is_some()).then(|| DebugLocs::new(&lines));
+    let (goal, items) = match &locs {
+        Some(locs) => crate::select_by_location(goal, items, |items, path, line| {
+            locs.items_for_line(&strs, items, path, line)
+                .into_iter()
+                .cloned()
+                .collect()
+        }),
+        None => (goal, items),
+    };
+    if let Some(locs) = locs.as_mut().filter(|_| fmt.rust) {
+        locs.load_sources(sysroot, fmt);
+    }
     let dump_ctx = LlvmDumpCtx {
         fmt,
         strings: &strs,
+        locs: locs.as_ref(),
     };
     if matches!(goal, ToDump::Interactive) {
         interactive_mode(&items, dump_ctx);
+    } else if let Some(matches) = get_all_matches(&goal, &items) {
+        dump_ctx.dump_items(fmt, matches)?;
     } else {
-        dump_ctx.dump_range(get_dump_range(goal, fmt, items))?;
+        dump_ctx.dump_item(fmt, get_dump_range(goal, fmt, items))?;
     }
     Ok(())
 }
@@ -87,6 +271,7 @@ pub fn dump_function(goal: ToDump, path: &Path, fmt: &Format) -> anyhow::Result<
 pub struct LlvmDumpCtx<'a> {
     fmt: &'a Format,
     strings: &'a [&'a str],
+    locs: Option<&'a DebugLocs<'a>>,
 }
 
 impl DumpRange for LlvmDumpCtx<'_> {
@@ -95,12 +280,40 @@ impl DumpRange for LlvmDumpCtx<'_> {
         range: Option<Range<usize>>,
         writer: &mut impl Write,
     ) -> anyhow::Result<()> {
-        let &Self { fmt, strings } = self;
+        let &Self {
+            fmt, strings, locs, ..
+        } = self;
         let strings = range.map_or(strings, |r| &strings[r]);
 
+        let mut prev_loc = None;
         for line in strings {
+            if locs.is_some() && is_debug_record(line) {
+                continue;
+            }
+            if let Some((locs, (file_id, line_no, _))) = locs
+                .filter(|_| fmt.rust)
+                .and_then(|locs| Some((locs, locs.location(line)?)))
+            {
+                if prev_loc != Some((file_id, line_no)) {
+                    prev_loc = Some((file_id, line_no));
+                    if let Some((fname, lines)) = locs.files.get(file_id) {
+                        let pos = format!("  ; {} : {line_no}", fname.display());
+                        writeln!(writer, "{}", color!(pos, OwoColorize::cyan))?;
+                        if let Some(rust_line) =
+                            lines.as_ref().and_then(|l| l.get(line_no as usize - 1))
+                        {
+                            let rust_line = format!("  ; {}", rust_line.trim_start());
+                            writeln!(writer, "{}", color!(rust_line, OwoColorize::bright_red))?;
+                        }
+                    }
+                }
+            }
             if line.starts_with("; ") {
                 writeln!(writer, "{}", color!(line, OwoColorize::bright_black))?;
+            } else if locs.is_some() {
+                let line = DBG.replace(line, "");
+                let line = demangle::contents(&line, fmt.full_name);
+                writeln!(writer, "{line}")?;
             } else {
                 let line = demangle::contents(line, fmt.full_name);
                 writeln!(writer, "{line}")?;
@@ -108,6 +321,31 @@ impl DumpRange for LlvmDumpCtx<'_> {
         }
         Ok(())
     }
+
+    fn range_to_json(&self, range: Option<Range<usize>>) -> anyhow::Result<serde_json::Value> {
+        let &Self { fmt, strings, locs } = self;
+        let strings = range.map_or(strings, |r| &strings[r]);
+        let Some(locs) = locs else {
+            let lines = strings
+                .iter()
+                .map(|line| demangle::contents(line, fmt.full_name))
+                .collect::<Vec<_>>();
+            return Ok(serde_json::json!({ "lines": lines }));
+        };
+        let mut lines = Vec::new();
+        let mut sources = Vec::new();
+        for line in strings.iter().filter(|line| !is_debug_record(line)) {
+            let source = locs.location(line).map(|loc| locs.loc_to_json(loc));
+            sources.push(source.unwrap_or(serde_json::Value::Null));
+            let line = DBG.replace(line, "");
+            lines.push(demangle::contents(&line, fmt.full_name).into_owned());
+        }
+        if !fmt.rust {
+            return Ok(serde_json::json!({ "lines": lines }));
+        }
+        // with `--rust` every line gets its Rust location, `null` if there's none
+        Ok(serde_json::json!({ "lines": lines, "sources": sources }))
+    }
 }
 
 /// try to print `goal` from `path`, collect available items otherwise
@@ -213,3 +451,128 @@ pub fn collect_or_dump(
 
     Ok(seen)
 }
+
+#[cfg(test)]
+// `rustc --crate-type=lib --crate-name foo -O -Cdebuginfo=2 --emit llvm-ir lib.rs`
+const DEBUG_INFO: &str = r#"; foo::bar
+; Function Attrs: mustprogress nofree noinline norecurse nosync nounwind nonlazybind willreturn memory(none) uwtable
+define noundef i32 @_ZN3foo3bar17h3c8e45046749b97cE(i32 noundef %x) unnamed_addr #0 !dbg !7 {
+start:
+    #dbg_value(i32 %x, !14, !DIExpression(), !16)
+  %0 = icmp eq i32 %x, 0, !dbg !17
+  %1 = mul i32 %x, 3, !dbg !17
+  %_0.sroa.0.0 = select i1 %0, i32 1, i32 %1, !dbg !17
+  ret i32 %_0.sroa.0.0, !dbg !18
+}
+
+!5 = distinct !DICompileUnit(language: DW_LANG_Rust, file: !6, producer: "clang LLVM (rustc version 1.95.0 (59807616e 2026-04-14))", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, splitDebugInlining: false, nameTableKind: None)
+!6 = !DIFile(filename: "lib.rs/@/foo.423bd65378e37648-cgu.0", directory: "/tmp/llfix")
+!7 = distinct !DISubprogram(name: "bar", linkageName: "_ZN3foo3bar17h3c8e45046749b97cE", scope: !9, file: !8, line: 2, type: !10, scopeLine: 2, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !5, templateParams: !15, retainedNodes: !13)
+!8 = !DIFile(filename: "lib.rs", directory: "/tmp/llfix", checksumkind: CSK_MD5, checksum: "27993fad9a91e6881d01cfb3665c50d9")
+!9 = !DINamespace(name: "foo", scope: null)
+!14 = !DILocalVariable(name: "x", arg: 1, scope: !7, file: !8, line: 2, type: !12)
+!16 = !DILocation(line: 0, scope: !7)
+!17 = !DILocation(line: 4, column: 8, scope: !7)
+!18 = !DILocation(line: 5, column: 2, scope: !7)
+"#;
+
+#[cfg(test)]
+fn debug_locs(lines: &CachedLines) -> DebugLocs<'_> {
+    let mut locs = DebugLocs::new(lines);
+    let source = "#[inline(never)]\npub fn bar(x: u32) -> u32 {\n    // comment\n    if x != 0 { x * 3 } else { 1 }\n}\n";
+    locs.files.get_mut("8").unwrap().1 = Some(CachedLines::without_ending(source.to_owned()));
+    locs
+}
+
+#[test]
+fn test_field() {
+    let node = r#"!DISubprogram(name: "bar", linkageName: "_ZN3foo3bar17h3c8e45046749b97cE", scope: !9, file: !8, line: 2, scopeLine: 2)"#;
+    assert_eq!(field(node, "name"), Some("bar"));
+    assert_eq!(field(node, "scope"), Some("!9"));
+    assert_eq!(field(node, "file"), Some("!8"));
+    // `line` is not a part of `scopeLine`
+    assert_eq!(field(node, "line"), Some("2"));
+    assert_eq!(field(node, "scopeLine"), Some("2"));
+    assert_eq!(field(node, "column"), None);
+    assert_eq!(
+        field(r#"!DIFile(filename: "a, b.rs")"#, "filename"),
+        Some("a, b.rs")
+    );
+}
+
+#[test]
+fn test_debug_locs() {
+    let lines = CachedLines::without_ending(DEBUG_INFO.to_owned());
+    let locs = debug_locs(&lines);
+    assert_eq!(locs.files.keys().copied().collect::<Vec<_>>(), ["6", "8"]);
+    assert_eq!(locs.files["8"].0, Path::new("/tmp/llfix/lib.rs"));
+
+    let located = lines
+        .iter()
+        .filter(|line| !is_debug_record(line))
+        .filter_map(|line| locs.location(line))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        located,
+        [("8", 4, 8), ("8", 4, 8), ("8", 4, 8), ("8", 5, 2)]
+    );
+    // line 0 means no location
+    assert_eq!(locs.location("  %x = add i32 %a, %b, !dbg !16"), None);
+    assert!(is_debug_record(
+        "    #dbg_value(i32 %x, !14, !DIExpression(), !16)"
+    ));
+    assert!(is_debug_record(
+        "  call void @llvm.dbg.value(metadata i32 %x, metadata !14, metadata !DIExpression()), !dbg !16"
+    ));
+    assert!(!is_debug_record("  ret i32 %_0.sroa.0.0, !dbg !18"));
+}
+
+#[test]
+fn test_range_to_json() {
+    use bpaf::Parser;
+    let fmt = crate::opts::format()
+        .to_options()
+        .run_inner(&["--rust", "--json"])
+        .unwrap();
+    let lines = CachedLines::without_ending(DEBUG_INFO.to_owned());
+    let locs = debug_locs(&lines);
+    let strings = lines.iter().collect::<Vec<_>>();
+    let dump_ctx = LlvmDumpCtx {
+        fmt: &fmt,
+        strings: &strings,
+        locs: Some(&locs),
+    };
+    let source = |line, column, rust| serde_json::json!({ "file": "/tmp/llfix/lib.rs", "line": line, "column": column, "rust": rust });
+    let cond = source(4, 8, "if x != 0 { x * 3 } else { 1 }");
+    assert_eq!(
+        dump_ctx.range_to_json(Some(3..9)).unwrap(),
+        serde_json::json!({
+            "lines": [
+                "start:",
+                "  %0 = icmp eq i32 %x, 0",
+                "  %1 = mul i32 %x, 3",
+                "  %_0.sroa.0.0 = select i1 %0, i32 1, i32 %1",
+                "  ret i32 %_0.sroa.0.0",
+            ],
+            "sources": [null, cond, cond, cond, source(5, 2, "}")],
+        })
+    );
+}
+
+#[test]
+fn test_items_for_line() {
+    let lines = CachedLines::without_ending(DEBUG_INFO.to_owned());
+    let locs = debug_locs(&lines);
+    let items = find_items(&lines);
+    let strs = lines.iter().collect::<Vec<_>>();
+    let names = |path: &str, line| {
+        locs.items_for_line(&strs, &items, Path::new(path), line)
+            .into_iter()
+            .map(|i| i.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("lib.rs", 4), ["foo::bar"]);
+    assert_eq!(names("/tmp/llfix/lib.rs", 5), ["foo::bar"]);
+    assert!(names("lib.rs", 3).is_empty());
+    assert!(names("src/lib.rs", 4).is_empty());
+}
@@ -1,22 +1,42 @@
 use anyhow::Context;
 use cargo_metadata::{Artifact, Message, MetadataCommand, Package};
-use cargo_show_asm::{asm, esafeprintln, llvm, mca, mir, opts};
+use cargo_show_asm::{asm, cgu, disasm, esafeprintln, llvm, mca, mir, opts};
 use once_cell::sync::Lazy;
 use std::{
     io::BufReader,
     path::{Path, PathBuf},
     process::Stdio,
+    time::SystemTime,
 };
 
 static CARGO_PATH: Lazy<PathBuf> =
     Lazy::new(|| std::env::var_os("CARGO").map_or_else(|| "cargo".into(), PathBuf::from));
 static RUSTC_PATH: Lazy<PathBuf> =
     Lazy::new(|| std::env::var_os("RUSTC").map_or_else(|| "rustc".into(), PathBuf::from));
+/// Compiler accepts `-Z` flags: nightly or locally built one, or the user asked for them
+/// with `RUSTC_BOOTSTRAP`
+static RUSTC_NIGHTLY: Lazy<bool> = Lazy::new(|| {
+    std::env::var_os("RUSTC_BOOTSTRAP").is_some()
+        || std::process::Command::new(&*RUSTC_PATH)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .is_some_and(|version| is_nightly(&version))
+});
+
+/// `rustc --version` output of a compiler that accepts `-Z` flags
+fn is_nightly(version: &str) -> bool {
+    version.contains("-nightly") || version.contains("-dev")
+}
 
 fn spawn_cargo(
     cargo: &opts::Cargo,
     format: &opts::Format,
     syntax: opts::Syntax,
+    by_location: bool,
     target_cpu: Option<&str>,
     focus_package: &Package,
     focus_artifact: &opts::Focus,
@@ -82,30 +102,132 @@ fn spawn_cargo(
     cmd.arg("--");
 
     // Rustc flags.
+    rustc_args(&mut cmd, cargo, format, syntax, by_location, target_cpu);
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+}
+
+/// Flags for rustc to produce a single file with generated code in the requested format
+///
+/// `by_location` asks for source locations needed to select functions by them
+fn rustc_args(
+    cmd: &mut std::process::Command,
+    cargo: &opts::Cargo,
+    format: &opts::Format,
+    syntax: opts::Syntax,
+    by_location: bool,
+    target_cpu: Option<&str>,
+) {
     cmd
         // Start with the user-supplied codegen flags, which we might need to override.
         .args(cargo.codegen.iter().flat_map(|c| ["-C", c]))
         // Next, we care about asm/wasm/llvm-ir/llvm-mac.
         .args(["--emit", syntax.emit()])
         // So only one file gets created.
-        .arg("-Ccodegen-units=1")
+        .args((!cargo.keep_codegen_units).then_some("-Ccodegen-units=1"))
         .args(syntax.format().iter().flat_map(|s| ["-C", s]))
         .args(target_cpu.iter().map(|cpu| format!("-Ctarget-cpu={cpu}")));
 
+    #[allow(clippy::enum_glob_use)]
+    use opts::Syntax::*;
+    // Debug info is needed to detect function boundaries in asm (Windows/Mac), and to map asm/wasm
+    // output to rust source.
+    let locations = format.rust || by_location;
+    if matches!(syntax, Intel | Att | Wasm | McaAtt | McaIntel)
+        || (locations && matches!(syntax, Llvm | LlvmInput))
     {
-        #[allow(clippy::enum_glob_use)]
-        use opts::Syntax::*;
-        // Debug info is needed to detect function boundaries in asm (Windows/Mac), and to map asm/wasm
-        // output to rust source.
-        if matches!(syntax, Intel | Att | Wasm | McaAtt | McaIntel) {
-            cmd.arg("-Cdebuginfo=2");
+        cmd.arg("-Cdebuginfo=2");
+    }
+    // Recent compilers don't include span comments in MIR unless asked to, and this
+    // option is only available on nightly
+    if locations && syntax == Mir {
+        if *RUSTC_NIGHTLY {
+            cmd.arg("-Zmir-include-spans=on");
+        } else {
+            esafeprintln!(
+                "Rust code and source locations for MIR need span comments, only a nightly compiler can include them"
+            );
         }
     }
+}
 
-    cmd.stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()
+/// Compile a standalone Rust file as a library with rustc, without a Cargo project
+///
+/// Generated code goes to `out_dir`
+fn compile_rs(opts: &opts::Options, source: &Path, out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let syntax = opts.syntax();
+    if !source.exists() {
+        anyhow::bail!("File {} doesn't exist", source.display());
+    }
+    std::fs::create_dir_all(out_dir)?;
+    let stem = out_dir.join(source.file_stem().context("Source file needs a name")?);
+    // with several codegen units there's one file per unit, remove ones from previous runs
+    for path in cgu::unit_files(&stem, syntax.ext(), SystemTime::UNIX_EPOCH)? {
+        std::fs::remove_file(path)?;
+    }
+
+    let mut cmd = rustc_rs_command(opts, source, out_dir)?;
+    if opts.format.verbosity > 0 {
+        esafeprintln!("Running {cmd:?}");
+    }
+
+    let status = cmd.status()?;
+    if !status.success() {
+        esafeprintln!("rustc failed with {status}");
+        cargo_show_asm::exit(101);
+    }
+    cgu::unit_files(&stem, syntax.ext(), SystemTime::UNIX_EPOCH)
+}
+
+/// rustc invocation for [`compile_rs`]
+fn rustc_rs_command(
+    opts: &opts::Options,
+    source: &Path,
+    out_dir: &Path,
+) -> anyhow::Result<std::process::Command> {
+    use std::ffi::OsStr;
+
+    let cargo = &opts.cargo;
+    let format = &opts.format;
+    let syntax = opts.syntax();
+    let mut cmd = std::process::Command::new(&*RUSTC_PATH);
+    cmd.arg("--crate-type=lib")
+        .arg(format!("--edition={}", opts.edition))
+        .args(["--color", if format.color { "always" } else { "never" }])
+        .args(cargo.target.iter().flat_map(|t| ["--target", t]))
+        .args((syntax == opts::Syntax::Wasm).then_some("--target=wasm32-unknown-unknown"))
+        .args(cargo.unstable.iter().flat_map(|z| ["-Z", z]));
+    match &cargo.compile_mode {
+        opts::CompileMode::Release => {
+            cmd.arg("-Copt-level=3");
+        }
+        opts::CompileMode::Custom(profile) if profile == "release" => {
+            cmd.arg("-Copt-level=3");
+        }
+        opts::CompileMode::Dev => {}
+        opts::CompileMode::Custom(profile) if profile == "dev" => {}
+        opts::CompileMode::Custom(profile) => {
+            anyhow::bail!("Profile {profile} needs a Cargo project, only dev and release can be used with --rs");
+        }
+    }
+    let by_location = opts.to_dump.source_location().is_some();
+    rustc_args(
+        &mut cmd,
+        cargo,
+        format,
+        syntax,
+        by_location,
+        opts.target_cpu.as_deref(),
+    );
+    cmd.args([OsStr::new("--out-dir"), out_dir.as_os_str()])
+        .arg(source)
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    Ok(cmd)
 }
 
 fn sysroot() -> anyhow::Result<PathBuf> {
@@ -127,12 +249,63 @@ fn sysroot() -> anyhow::Result<PathBuf> {
     ))
 }
 
+/// Comparing two builds renders a single function or the whole file as text, reject anything
+/// else before building
+fn check_diff(opts: &opts::Options) -> anyhow::Result<()> {
+    use opts::{Syntax, ToDump};
+
+    if opts.diff.is_empty() {
+        return Ok(());
+    }
+    if opts.file.is_some() || opts.rs.is_some() {
+        anyhow::bail!("Comparing two builds needs cargo, it can't be used with --file or --rs");
+    }
+    if !matches!(opts.syntax(), Syntax::Intel | Syntax::Att | Syntax::Wasm) {
+        anyhow::bail!("Comparing two builds is only supported for assembly output");
+    }
+    match &opts.to_dump {
+        ToDump::Report { .. } => anyhow::bail!("Reports can't be compared between two builds"),
+        ToDump::Interactive => {
+            anyhow::bail!("Interactive mode can't be used when comparing two builds")
+        }
+        ToDump::Function {
+            with_callees,
+            all_matches,
+            ..
+        } => {
+            for (enabled, name) in [
+                (with_callees.is_some(), "--with-callees"),
+                (*all_matches, "--all-matches"),
+            ] {
+                if enabled {
+                    anyhow::bail!("Comparing two builds needs a single function, drop {name}");
+                }
+            }
+        }
+        ToDump::Everything | ToDump::ByIndex { .. } | ToDump::Unspecified => {}
+    }
+    for (enabled, name) in [
+        (
+            opts.format.mode.is_some(),
+            opts.format.mode.map_or("", opts::Mode::flag),
+        ),
+        (opts.format.json, "--json"),
+        (opts.format.check.is_some(), "--check"),
+        (opts.format.inner_loops, "--inner-loops"),
+    ] {
+        if enabled {
+            anyhow::bail!("{name} can't be used when comparing two builds");
+        }
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_lines)]
 fn main() -> anyhow::Result<()> {
     use opts::Syntax;
 
-    let opts = opts::options().run();
-    owo_colors::set_override(opts.format.color);
+    let mut opts = opts::options().run();
+    owo_colors::set_override(opts.format.color && !opts.format.is_machine_readable());
 
     #[cfg(feature = "ipc")]
     if let Some(client) = opts.client {
@@ -140,11 +313,164 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if opts.file.is_some() && opts.rs.is_some() {
+        anyhow::bail!("--file and --rs can't be used together");
+    }
+
+    check_diff(&opts)?;
+
+    if (opts.file.is_some() || opts.rs.is_some()) && opts.cargo.lto {
+        anyhow::bail!(
+            "--lto needs a Cargo target that gets linked, it can't be used with --file or --rs"
+        );
+    }
+
+    if opts.format.inlined && !opts.format.rust {
+        anyhow::bail!("--inlined shows inlining chains for Rust code, it needs --rust");
+    }
+
+    if opts.format.bless && opts.format.check.is_none() {
+        anyhow::bail!("--bless needs a snapshot file passed with --check");
+    }
+
+    if let opts::ToDump::Function {
+        with_callees,
+        all_matches,
+        nth,
+        ..
+    } = &opts.to_dump
+    {
+        if opts.format.check.is_some() && (with_callees.is_some() || *all_matches) {
+            anyhow::bail!(
+                "--check only works with a single function, drop --with-callees and --all-matches"
+            );
+        }
+        if *all_matches && nth.is_some() {
+            anyhow::bail!("--all-matches dumps every matching function, INDEX is not needed");
+        }
+        if *all_matches && with_callees.is_some() {
+            anyhow::bail!("--all-matches can't be combined with --with-callees");
+        }
+        if opts.format.inner_loops && (with_callees.is_some() || *all_matches) {
+            anyhow::bail!("--inner-loops only works with a single function");
+        }
+        if let Some(mode) = opts.format.mode.filter(|&m| m != opts::Mode::Stats) {
+            if *all_matches {
+                anyhow::bail!(
+                    "{} only works with a single function, drop --all-matches",
+                    mode.flag()
+                );
+            }
+        }
+    }
+
+    #[cfg(not(feature = "disasm"))]
+    if opts.syntax() == Syntax::Disasm {
+        anyhow::bail!(
+            "cargo-show-asm was built without the disassembler, install it with `-F disasm`"
+        );
+    }
+
+    if opts.syntax() == Syntax::Disasm
+        && (opts.format.rust
+            || opts.format.mode == Some(opts::Mode::BySource)
+            || opts.to_dump.source_location().is_some())
+    {
+        anyhow::bail!(
+            "Disassembled code has no source locations, --rust, --by-source and FILE:LINE need assembly output"
+        );
+    }
+
+    if opts.format.inner_loops
+        && !matches!(
+            opts.syntax(),
+            Syntax::Intel
+                | Syntax::Att
+                | Syntax::Wasm
+                | Syntax::McaAtt
+                | Syntax::McaIntel
+                | Syntax::Disasm
+        )
+    {
+        anyhow::bail!("--inner-loops is only supported for assembly and llvm-mca output");
+    }
+
+    if !matches!(
+        opts.syntax(),
+        Syntax::Intel | Syntax::Att | Syntax::Wasm | Syntax::Disasm
+    ) {
+        if let Some(mode) = opts.format.mode {
+            anyhow::bail!("{} is only supported for assembly output", mode.flag());
+        }
+        for (enabled, name) in [
+            (opts.format.inlined, "--inlined"),
+            (opts.format.constants, "--constants"),
+            (opts.format.dyn_calls, "--dyn-calls"),
+            (opts.format.visualize_jumps, "--visualize-jumps"),
+            (opts.format.loops, "--loops"),
+            (
+                matches!(
+                    opts.to_dump,
+                    opts::ToDump::Function {
+                        with_callees: Some(_),
+                        ..
+                    }
+                ),
+                "--with-callees",
+            ),
+            (
+                matches!(
+                    opts.to_dump,
+                    opts::ToDump::Report {
+                        report: opts::Report::Calls,
+                        ..
+                    }
+                ),
+                "--report calls",
+            ),
+            (
+                matches!(
+                    opts.to_dump,
+                    opts::ToDump::Report {
+                        report: opts::Report::Vtables,
+                        ..
+                    }
+                ),
+                "--report vtables",
+            ),
+        ] {
+            if enabled {
+                anyhow::bail!("{name} is only supported for assembly output");
+            }
+        }
+    }
+
     let sysroot = sysroot()?;
     if opts.format.verbosity > 0 {
         esafeprintln!("Found sysroot: {}", sysroot.display());
     }
 
+    if let Some(file) = opts.file.take() {
+        return dump_file(opts, &file, &sysroot);
+    }
+
+    if let Some(source) = opts.rs.take() {
+        // without a target directory every run gets its own temporary one
+        let temp_dir;
+        let out_dir = match &opts.cargo.target_dir {
+            Some(dir) => dir.as_path(),
+            None => {
+                temp_dir = cargo_show_asm::TempPath::new(
+                    std::env::temp_dir().join(format!("cargo-show-asm-rs-{}", std::process::id())),
+                );
+                &*temp_dir
+            }
+        };
+        let paths = compile_rs(&opts, &source, out_dir)?;
+        let roots = [std::env::current_dir()?];
+        return dump_units(&opts, &paths, &sysroot, &roots);
+    }
+
     let unstable = opts
         .cargo
         .unstable
@@ -159,7 +485,7 @@ fn main() -> anyhow::Result<()> {
         .no_deps()
         .exec()?;
 
-    let focus_package = match opts.select_fragment.package {
+    let focus_package = match opts.select_fragment.package.take() {
         Some(name) => metadata
             .packages
             .iter()
@@ -178,7 +504,7 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
-    let focus_artifact = match opts.select_fragment.focus {
+    let focus_artifact = match opts.select_fragment.focus.take() {
         Some(focus) => focus,
         None => match focus_package.targets.len() {
             0 => anyhow::bail!("No targets found"),
@@ -198,15 +524,151 @@ fn main() -> anyhow::Result<()> {
         },
     };
 
-    let mut cargo_child = spawn_cargo(
+    if opts.cargo.lto && matches!(focus_artifact, opts::Focus::Lib) {
+        let lib = focus_package
+            .targets
+            .iter()
+            .find(|t| matches!(opts::Focus::try_from(*t), Ok(opts::Focus::Lib)));
+        if let Some(lib) = lib.filter(|lib| !opts::gets_linked(&lib.kind)) {
+            anyhow::bail!(
+                "LTO only happens when a crate gets linked, {:?} target doesn't use it",
+                lib.kind
+            );
+        }
+    }
+
+    let asm_paths = build_and_locate(
         &opts.cargo,
         &opts.format,
-        opts.syntax,
+        opts.syntax(),
+        opts.to_dump.source_location().is_some(),
         opts.target_cpu.as_deref(),
         focus_package,
         &focus_artifact,
     )?;
 
+    if !opts.diff.is_empty() {
+        let (cargo, target_cpu) = opts.diff.apply(
+            &opts.cargo,
+            opts.target_cpu.as_deref(),
+            metadata.target_directory.as_std_path(),
+        );
+        let other_paths = build_and_locate(
+            &cargo,
+            &opts.format,
+            opts.syntax(),
+            opts.to_dump.source_location().is_some(),
+            target_cpu.as_deref(),
+            focus_package,
+            &focus_artifact,
+        )?;
+        let ([asm_path], [other_path]) = (asm_paths.as_slice(), other_paths.as_slice()) else {
+            anyhow::bail!("Comparing two builds needs a single codegen unit in both of them");
+        };
+        return asm::diff_function(opts.to_dump, [asm_path, other_path], &sysroot, &opts.format);
+    }
+
+    // MIR spans can point to files in other packages
+    let roots = std::iter::once(metadata.workspace_root.as_std_path())
+        .chain(
+            metadata
+                .packages
+                .iter()
+                .filter_map(|package| package.manifest_path.parent())
+                .map(|dir| dir.as_std_path()),
+        )
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    dump_units(&opts, &asm_paths, &sysroot, &roots)
+}
+
+/// Dump the item selected by `opts` from a single file or from several codegen units
+fn dump_units(
+    opts: &opts::Options,
+    paths: &[PathBuf],
+    sysroot: &Path,
+    roots: &[PathBuf],
+) -> anyhow::Result<()> {
+    match paths {
+        [] => anyhow::bail!("No files with generated code found"),
+        [path] => dump(opts, opts.to_dump.clone(), path, sysroot, roots),
+        _ => cgu::dump_function(
+            opts.to_dump.clone(),
+            paths,
+            opts.syntax(),
+            &opts.format,
+            |goal, path| dump(opts, goal, path, sysroot, roots),
+        ),
+    }
+}
+
+/// Dump `goal` from the file with generated code
+fn dump(
+    opts: &opts::Options,
+    goal: opts::ToDump,
+    path: &Path,
+    sysroot: &Path,
+    roots: &[PathBuf],
+) -> anyhow::Result<()> {
+    use opts::Syntax;
+    match opts.syntax() {
+        Syntax::Intel | Syntax::Att | Syntax::Wasm => {
+            asm::dump_function(goal, path, sysroot, &opts.format)
+        }
+        Syntax::McaAtt | Syntax::McaIntel => mca::dump_function(
+            goal,
+            path,
+            &opts.format,
+            &opts.mca_arg,
+            opts.syntax() == Syntax::McaIntel,
+            &opts.cargo.target,
+            &opts.target_cpu,
+        ),
+        Syntax::Llvm | Syntax::LlvmInput => llvm::dump_function(goal, path, sysroot, &opts.format),
+        Syntax::Mir => mir::dump_function(goal, path, sysroot, roots, &opts.format),
+        Syntax::Disasm => disasm::dump_function(goal, path, sysroot, &opts.format),
+    }
+}
+
+/// Dump the item selected by `opts` from an existing file, `-` stands for stdin
+///
+/// Stdin is saved to a temporary file first since all the dumpers work with files
+fn dump_file(opts: opts::Options, file: &Path, sysroot: &Path) -> anyhow::Result<()> {
+    let roots = [std::env::current_dir()?];
+    if file != Path::new("-") {
+        if !file.exists() {
+            anyhow::bail!("File {} doesn't exist", file.display());
+        }
+        return dump(&opts, opts.to_dump.clone(), file, sysroot, &roots);
+    }
+
+    let path = cargo_show_asm::save_input(std::io::stdin(), opts.syntax())
+        .context("Failed to read stdin")?;
+    dump(&opts, opts.to_dump.clone(), &path, sysroot, &roots)
+}
+
+/// Run cargo and locate files with generated code for `focus_artifact`
+///
+/// There's one file unless codegen units from the profile are kept
+fn build_and_locate(
+    cargo: &opts::Cargo,
+    format: &opts::Format,
+    syntax: opts::Syntax,
+    by_location: bool,
+    target_cpu: Option<&str>,
+    focus_package: &Package,
+    focus_artifact: &opts::Focus,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut cargo_child = spawn_cargo(
+        cargo,
+        format,
+        syntax,
+        by_location,
+        target_cpu,
+        focus_package,
+        focus_artifact,
+    )?;
+
     let mut result_artifact = None;
     let mut success = false;
     for msg in Message::parse_stream(BufReader::new(cargo_child.stdout.take().unwrap())) {
@@ -226,37 +688,31 @@ fn main() -> anyhow::Result<()> {
     if !success {
         let status = cargo_child.wait()?;
         esafeprintln!("Cargo failed with {status}");
-        std::process::exit(101);
+        cargo_show_asm::exit(101);
     }
     let artifact = result_artifact.context("No artifact found")?;
 
-    if opts.format.verbosity > 0 {
+    if format.verbosity > 0 {
         esafeprintln!("Artifact files: {:?}", artifact.filenames);
     }
 
-    let asm_path = locate_asm_path_via_artifact(&artifact, opts.syntax.ext())?;
-    if opts.format.verbosity > 0 {
-        esafeprintln!("Asm file: {}", asm_path.display());
+    // thin LTO creates one file per module, crates it inlined code from included
+    if cargo.keep_codegen_units || cargo.lto {
+        // dep-info is written before any code, older files are left from previous builds
+        let dep_info = locate_asm_path_via_artifact(&artifact, "d")?;
+        let since = std::fs::metadata(&dep_info)?.modified()?;
+        let paths = cgu::unit_files(&dep_info.with_extension(""), syntax.ext(), since)?;
+        if format.verbosity > 0 {
+            esafeprintln!("Codegen unit files: {paths:?}");
+        }
+        return Ok(paths);
     }
 
-    match opts.syntax {
-        Syntax::Intel | Syntax::Att | Syntax::Wasm => {
-            asm::dump_function(opts.to_dump, &asm_path, &sysroot, &opts.format)
-        }
-        Syntax::McaAtt | Syntax::McaIntel => mca::dump_function(
-            opts.to_dump,
-            &asm_path,
-            &opts.format,
-            &opts.mca_arg,
-            opts.syntax == Syntax::McaIntel,
-            &opts.cargo.target,
-            &opts.target_cpu,
-        ),
-        Syntax::Llvm | Syntax::LlvmInput => {
-            llvm::dump_function(opts.to_dump, &asm_path, &opts.format)
-        }
-        Syntax::Mir => mir::dump_function(opts.to_dump, &asm_path, &opts.format),
+    let asm_path = locate_asm_path_via_artifact(&artifact, syntax.ext())?;
+    if format.verbosity > 0 {
+        esafeprintln!("Asm file: {}", asm_path.display());
     }
+    Ok(vec![asm_path])
 }
 
 fn locate_asm_path_via_artifact(artifact: &Artifact, expect_ext: &str) -> anyhow::Result<PathBuf> {
@@ -307,7 +763,7 @@ fn locate_asm_path_via_artifact(artifact: &Artifact, expect_ext: &str) -> anyhow
         let rlib_path = artifact
             .filenames
             .iter()
-            .find(|f| f.extension().map_or(false, |e| e == "rlib"))
+            .find(|f| f.extension() == Some("rlib"))
             .expect("No rlib?");
         let deps_dir = rlib_path.with_file_name("deps");
 
@@ -343,7 +799,7 @@ fn locate_asm_path_via_artifact(artifact: &Artifact, expect_ext: &str) -> anyhow
             .iter()
             .find(|f| {
                 f.extension()
-                    .map_or(false, |e| ["so", "dylib", "dll"].contains(&e))
+                    .is_some_and(|e| ["so", "dylib", "dll"].contains(&e))
             })
             .expect("No cdylib?");
         let deps_dir = cdylib_path.with_file_name("deps");
@@ -351,7 +807,9 @@ fn locate_asm_path_via_artifact(artifact: &Artifact, expect_ext: &str) -> anyhow
             let entry = entry?;
             let maybe_origin = entry.path();
             if same_contents(cdylib_path, &maybe_origin)? {
-                let Some(name) = maybe_origin.file_name() else { continue };
+                let Some(name) = maybe_origin.file_name() else {
+                    continue;
+                };
                 let Some(name) = name.to_str() else { continue };
                 let name = name.strip_prefix("lib").unwrap_or(name);
                 // on windows this is xx.dll -> xx.s, no lib....
@@ -404,3 +862,99 @@ fn same_contents<A: AsRef<Path>, B: AsRef<Path>>(a: &A, b: &B) -> anyhow::Result
         || (std::fs::metadata(a)?.len() == std::fs::metadata(b)?.len()
             && std::fs::read(a)? == std::fs::read(b)?))
 }
+
+#[test]
+fn rustc_args_for_rs_file() {
+    let args = |args: &[&str]| {
+        let opts = opts::options()
+            .run_inner([&["--rs", "sample.rs"], args].concat().as_slice())
+            .unwrap();
+        let cmd = rustc_rs_command(&opts, Path::new("sample.rs"), Path::new("out")).unwrap();
+        cmd.get_args()
+            .map(|arg| arg.to_str().unwrap().to_owned())
+            .collect::<Vec<_>>()
+    };
+    let has = |args: &[String], arg: &str| args.iter().any(|a| a == arg);
+
+    let default = args(&[]);
+    assert!(has(&default, "--crate-type=lib"));
+    assert!(has(&default, "--edition=2021"));
+    assert!(has(&default, "-Copt-level=3"));
+    assert!(default.ends_with(&["--out-dir".into(), "out".into(), "sample.rs".into()]));
+
+    let dev = args(&["--edition", "2024", "--dev"]);
+    assert!(has(&dev, "--edition=2024"));
+    assert!(!has(&dev, "-Copt-level=3"));
+    assert!(!has(&args(&["--profile", "dev"]), "-Copt-level=3"));
+    assert!(has(&args(&["--wasm"]), "--target=wasm32-unknown-unknown"));
+
+    let opts = opts::options()
+        .run_inner(&["--rs", "sample.rs", "--profile", "bench"])
+        .unwrap();
+    assert!(rustc_rs_command(&opts, Path::new("sample.rs"), Path::new("out")).is_err());
+}
+
+#[test]
+fn nightly_versions() {
+    assert!(is_nightly("rustc 1.92.0-nightly (e50aa6fba 2025-10-01)"));
+    assert!(is_nightly("rustc 1.92.0-dev"));
+    assert!(!is_nightly("rustc 1.90.0 (1159e78c4 2025-09-14)"));
+    assert!(!is_nightly("rustc 1.91.0-beta.5 (a0c9c5a4c 2025-10-10)"));
+}
+
+#[test]
+fn diff_rejects_other_modes() {
+    let check = |args: &[&str]| {
+        let opts = opts::options()
+            .run_inner([&["--diff-target-cpu", "native"], args].concat().as_slice())
+            .unwrap();
+        check_diff(&opts).map_err(|e| e.to_string())
+    };
+    assert!(check(&["foo"]).is_ok());
+    assert!(check(&["--everything"]).is_ok());
+    assert!(check(&["--rust", "--constants", "foo"]).is_ok());
+
+    let reports = "Reports can't be compared between two builds";
+    assert_eq!(check(&["--report", "sizes"]).unwrap_err(), reports);
+    assert_eq!(check(&["--report", "calls"]).unwrap_err(), reports);
+    assert_eq!(
+        check(&["-i"]).unwrap_err(),
+        "Interactive mode can't be used when comparing two builds"
+    );
+    for flag in [
+        "--panics",
+        "--cfg",
+        "--callees",
+        "--callers",
+        "--by-source",
+        "--simd-report",
+        "--stats",
+        "--json",
+        "--inner-loops",
+    ] {
+        assert_eq!(
+            check(&[flag, "foo"]).unwrap_err(),
+            format!("{flag} can't be used when comparing two builds")
+        );
+    }
+    assert_eq!(
+        check(&["--check", "foo.snap", "foo"]).unwrap_err(),
+        "--check can't be used when comparing two builds"
+    );
+    assert_eq!(
+        check(&["--with-callees", "foo"]).unwrap_err(),
+        "Comparing two builds needs a single function, drop --with-callees"
+    );
+    assert_eq!(
+        check(&["--all-matches", "foo"]).unwrap_err(),
+        "Comparing two builds needs a single function, drop --all-matches"
+    );
+    assert_eq!(
+        check(&["--llvm", "foo"]).unwrap_err(),
+        "Comparing two builds is only supported for assembly output"
+    );
+    assert_eq!(
+        check(&["--file", "foo.s", "foo"]).unwrap_err(),
+        "Comparing two builds needs cargo, it can't be used with --file or --rs"
+    );
+}
@@ -1,13 +1,15 @@
 use std::{
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Write},
+    ops::Range,
     path::Path,
     process::{Command, Stdio},
 };
 
 use crate::{
-    demangle, esafeprintln, get_dump_range, interactive_mode,
-    opts::{Format, ToDump},
-    safeprintln, DumpRange,
+    asm::InnerLoop,
+    demangle, esafeprintln, get_all_matches, get_dump_range, interactive_mode,
+    opts::{Format, Report, ToDump},
+    report, safeprintln, DumpRange, Item,
 };
 
 /// dump mca analysis
@@ -26,6 +28,21 @@ pub fn dump_function(
     let contents = std::fs::read_to_string(path)?;
     let statements = crate::asm::parse_file(&contents)?;
     let functions = crate::asm::find_items(&statements);
+    let (goal, functions) = crate::asm::select_by_location(goal, &statements, functions);
+
+    if let ToDump::Report { report, sort_by } = goal {
+        match report {
+            Report::Sizes => {
+                let sizes = functions.iter().map(|(item, range)| {
+                    let count = crate::asm::instruction_count(&statements[range.clone()]);
+                    (item, count)
+                });
+                return report::dump_sizes(sizes, sort_by, fmt);
+            }
+            Report::Calls => anyhow::bail!("Call graph is only supported for assembly output"),
+            Report::Vtables => anyhow::bail!("Vtables are only supported for assembly output"),
+        }
+    }
 
     let lines = contents.lines().collect::<Vec<_>>();
     let dump_ctx = McaDump {
@@ -39,12 +56,54 @@ pub fn dump_function(
 
     if matches!(goal, ToDump::Interactive) {
         interactive_mode(&functions, dump_ctx);
+    } else if let Some(matches) = get_all_matches(&goal, &functions) {
+        dump_ctx.dump_items(fmt, matches)?;
+    } else if fmt.inner_loops {
+        let item = get_dump_range(goal, fmt, functions);
+        let loops = item
+            .as_ref()
+            .map(|(_, range)| crate::asm::inner_loops(&statements, range.clone()));
+        dump_inner_loops(&dump_ctx, item, loops)?;
     } else {
         let range = get_dump_range(goal, fmt, functions);
         if fmt.verbosity > 0 && range.is_none() {
             safeprintln!("Going to use the whole file");
         }
-        dump_ctx.dump_range(range)?;
+        dump_ctx.dump_item(fmt, range)?;
+    }
+    Ok(())
+}
+
+/// Run llvm-mca on every innermost loop of an item separately
+///
+/// Lines of a loop are fed to llvm-mca in the order they appear in the function
+fn dump_inner_loops(
+    dump_ctx: &McaDump,
+    item: Option<(Item, Range<usize>)>,
+    loops: Option<Vec<InnerLoop>>,
+) -> anyhow::Result<()> {
+    let fmt = dump_ctx.fmt;
+    let (Some((item, _)), Some(loops)) = (item, loops) else {
+        return dump_ctx.dump_item(fmt, None);
+    };
+    if loops.is_empty() {
+        anyhow::bail!("No loops found in {}", item.name);
+    }
+
+    let mut writer = std::io::stdout();
+    for (ix, lp) in loops.iter().enumerate() {
+        if ix > 0 {
+            safeprintln!();
+        }
+        safeprintln!("# loop at {}, depth {}", lp.header, lp.depth);
+        let lines = lp
+            .ranges
+            .iter()
+            .flat_map(|r| &dump_ctx.lines[r.clone()])
+            .copied();
+        if dump_ctx.run_mca(lines, &mut writer).is_err() || writer.flush().is_err() {
+            crate::exit(0); // Exit when stdout is closed
+        }
     }
     Ok(())
 }
@@ -58,10 +117,11 @@ struct McaDump<'a> {
     lines: &'a [&'a str],
 }
 
-impl DumpRange for McaDump<'_> {
-    fn dump_range_into_writer(
+impl<'a> McaDump<'a> {
+    /// Feed `lines` of assembly to llvm-mca and write its report
+    fn run_mca<'b>(
         &self,
-        range: Option<std::ops::Range<usize>>,
+        lines: impl IntoIterator<Item = &'b str>,
         writer: &mut impl std::io::Write,
     ) -> anyhow::Result<()> {
         use std::io::Write;
@@ -71,9 +131,8 @@ impl DumpRange for McaDump<'_> {
             mca_intel,
             triple,
             target_cpu,
-            lines,
+            ..
         } = self;
-        let lines = range.map_or(lines, |r| &lines[r]);
 
         let mut mca = Command::new("llvm-mca");
         mca.args(mca_args)
@@ -92,7 +151,7 @@ impl DumpRange for McaDump<'_> {
             Err(err) => {
                 esafeprintln!("Failed to start llvm-mca, do you have it installed? The error was");
                 esafeprintln!("{err}");
-                std::process::exit(1);
+                crate::exit(1);
             }
         };
 
@@ -131,3 +190,27 @@ impl DumpRange for McaDump<'_> {
         Ok(())
     }
 }
+
+impl DumpRange for McaDump<'_> {
+    fn dump_range_into_writer(
+        &self,
+        range: Option<std::ops::Range<usize>>,
+        writer: &mut impl std::io::Write,
+    ) -> anyhow::Result<()> {
+        let lines = range.map_or(self.lines, |r| &self.lines[r]);
+        self.run_mca(lines.iter().copied(), writer)
+    }
+
+    fn range_to_json(
+        &self,
+        range: Option<std::ops::Range<usize>>,
+    ) -> anyhow::Result<serde_json::Value> {
+        let mut buf = Vec::new();
+        self.dump_range_into_writer(range, &mut buf)?;
+        let lines = String::from_utf8(buf)?
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        Ok(serde_json::json!({ "lines": lines }))
+    }
+}
@@ -1,13 +1,21 @@
 use crate::{
     cached_lines::CachedLines,
-    color, get_dump_range, interactive_mode,
-    opts::{Format, ToDump},
-    DumpRange, Item,
+    color, get_all_matches, get_dump_range, interactive_mode,
+    opts::{Format, Report, ToDump},
+    report, safeprintln, DumpRange, Item,
 };
+use once_cell::sync::Lazy;
 use owo_colors::OwoColorize;
-use std::{collections::BTreeMap, io::Write, ops::Range, path::Path};
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
+#[must_use]
+pub fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
     let mut res = BTreeMap::new();
     let mut current_item = None::<Item>;
     let mut block_start = None;
@@ -49,10 +57,88 @@ fn find_items(lines: &CachedLines) -> BTreeMap<Item, Range<usize>> {
     res
 }
 
+// `// scope 3 at src/lib.rs:10:5: 10:20`, path itself can contain `:`
+static SPAN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\s*// scope \d+ at (.+):(\d+):\d+: \d+:\d+$").expect("regexp should be valid")
+});
+
+/// Span comment at the end of a MIR statement or terminator: its position and file with line
+fn span(line: &str) -> Option<(usize, &str, u64)> {
+    let caps = SPAN.captures(line)?;
+    let start = caps.get(0)?.start();
+    Some((start, caps.get(1)?.as_str(), caps[2].parse().ok()?))
+}
+
+/// File a span comment refers to
+///
+/// Cargo passes rustc paths relative to the workspace root for packages inside of it and
+/// absolute paths for everything else: registry and git dependencies, path dependencies
+/// outside of the workspace, standard library. A relative path that doesn't exist in the
+/// first root came from a crate built in a different workspace and is looked up in the rest
+fn resolve(name: &str, roots: &[PathBuf]) -> PathBuf {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    roots
+        .iter()
+        .map(|root| root.join(path))
+        .find(|candidate| candidate.exists())
+        .or_else(|| roots.first().map(|root| root.join(path)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Rust sources referenced by span comments
+fn load_sources<'a>(
+    lines: &'a CachedLines,
+    sysroot: &Path,
+    roots: &[PathBuf],
+    fmt: &Format,
+) -> BTreeMap<&'a str, (PathBuf, Option<CachedLines>)> {
+    let mut files = BTreeMap::new();
+    for (_, name, _) in lines.iter().filter_map(span) {
+        files.entry(name).or_insert_with(|| {
+            let path = resolve(name, roots);
+            if fmt.verbosity > 1 {
+                safeprintln!("Reading file {}", path.display());
+            }
+            let lines = crate::asm::locate_sources(sysroot, &path).and_then(|source| {
+                let sources = std::fs::read_to_string(source).ok()?;
+                Some(CachedLines::without_ending(sources))
+            });
+            (path, lines)
+        });
+    }
+    files
+}
+
+/// Functions with code generated from `line` of `path` according to span comments
+fn items_for_line<'a>(
+    strings: &[&str],
+    items: &'a BTreeMap<Item, Range<usize>>,
+    roots: &[PathBuf],
+    path: &Path,
+    line: u64,
+) -> Vec<&'a Item> {
+    let mut files = BTreeMap::new();
+    crate::items_for_line(items, line, |range| {
+        strings[range]
+            .iter()
+            .filter_map(|line| span(line))
+            .filter(|(_, name, _)| {
+                *files
+                    .entry(*name)
+                    .or_insert_with(|| crate::is_source_file(&resolve(name, roots), path))
+            })
+            .map(|(_, _, line)| line)
+            .collect::<Vec<_>>()
+    })
+}
+
 struct MirDumpCtx<'a> {
-    #[allow(dead_code)]
     fmt: &'a Format,
     strings: &'a [&'a str],
+    sources: Option<&'a BTreeMap<&'a str, (PathBuf, Option<CachedLines>)>>,
 }
 
 impl DumpRange for MirDumpCtx<'_> {
@@ -61,10 +147,36 @@ impl DumpRange for MirDumpCtx<'_> {
         range: Option<Range<usize>>,
         writer: &mut impl Write,
     ) -> anyhow::Result<()> {
-        let strings = range.map_or(self.strings, |r| &self.strings[r]);
+        let &Self {
+            fmt,
+            strings,
+            sources,
+        } = self;
+        let strings = range.map_or(strings, |r| &strings[r]);
 
+        let mut prev_loc = None;
         for line in strings {
-            if let Some(ix) = line.rfind("//") {
+            let span = span(line);
+            if let Some(((_, name, line_no), sources)) = span.zip(sources) {
+                if prev_loc != Some((name, line_no)) {
+                    prev_loc = Some((name, line_no));
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    if let Some((path, lines)) = sources.get(name) {
+                        let pos = format!("{indent}// {} : {line_no}", path.display());
+                        writeln!(writer, "{}", color!(pos, OwoColorize::cyan))?;
+                        if let Some(rust_line) = lines
+                            .as_ref()
+                            .and_then(|l| l.get((line_no as usize).checked_sub(1)?))
+                        {
+                            let rust_line = format!("{indent}// {}", rust_line.trim_start());
+                            writeln!(writer, "{}", color!(rust_line, OwoColorize::bright_red))?;
+                        }
+                    }
+                }
+            }
+            if let Some((ix, _, _)) = span.filter(|_| fmt.simplify) {
+                writeln!(writer, "{}", &line[..ix])?;
+            } else if let Some(ix) = line.rfind("//") {
                 writeln!(
                     writer,
                     "{}{}",
@@ -77,24 +189,139 @@ impl DumpRange for MirDumpCtx<'_> {
         }
         Ok(())
     }
+
+    fn range_to_json(&self, range: Option<Range<usize>>) -> anyhow::Result<serde_json::Value> {
+        let strings = range.map_or(self.strings, |r| &self.strings[r]);
+        Ok(serde_json::json!({ "lines": strings }))
+    }
 }
 
 /// dump mir code
 ///
+/// `roots` are used to resolve relative paths in span comments: workspace root first,
+/// followed by directories of other packages
+///
 /// # Errors
 /// Reports file IO errors
-pub fn dump_function(goal: ToDump, path: &Path, fmt: &Format) -> anyhow::Result<()> {
+pub fn dump_function(
+    goal: ToDump,
+    path: &Path,
+    sysroot: &Path,
+    roots: &[PathBuf],
+    fmt: &Format,
+) -> anyhow::Result<()> {
     let lines = CachedLines::without_ending(std::fs::read_to_string(path)?);
     let items = find_items(&lines);
+    if let ToDump::Report { report, sort_by } = goal {
+        match report {
+            Report::Sizes => {
+                let sizes = items.iter().map(|(item, range)| {
+                    // statements and terminators live inside of basic blocks, skip debug info,
+                    // local declarations and scopes
+                    let count = range
+                        .clone()
+                        .filter_map(|ix| lines.get(ix))
+                        .filter(|line| line.starts_with("        "))
+                        .map(str::trim_start)
+                        .filter(|line| {
+                            !["debug ", "let ", "scope ", "}", "//"]
+                                .iter()
+                                .any(|skip| line.starts_with(skip))
+                        })
+                        .count();
+                    (item, count)
+                });
+                return report::dump_sizes(sizes, sort_by, fmt);
+            }
+            Report::Calls => anyhow::bail!("Call graph is only supported for assembly output"),
+            Report::Vtables => anyhow::bail!("Vtables are only supported for assembly output"),
+        }
+    }
     let strs = lines.iter().collect::<Vec<_>>();
+    let (goal, items) = crate::select_by_location(goal, items, |items, path, line| {
+        items_for_line(&strs, items, roots, path, line)
+            .into_iter()
+            .cloned()
+            .collect()
+    });
+    let sources = fmt.rust.then(|| load_sources(&lines, sysroot, roots, fmt));
     let dump_ctx = MirDumpCtx {
         fmt,
         strings: &strs,
+        sources: sources.as_ref(),
     };
     if matches!(goal, ToDump::Interactive) {
         interactive_mode(&items, dump_ctx);
+    } else if let Some(matches) = get_all_matches(&goal, &items) {
+        dump_ctx.dump_items(fmt, matches)?;
     } else {
-        dump_ctx.dump_range(get_dump_range(goal, fmt, items))?;
+        dump_ctx.dump_item(fmt, get_dump_range(goal, fmt, items))?;
     }
     Ok(())
 }
+
+#[test]
+fn test_span() {
+    let line = "        _0 = const 0_u32;                // scope 0 at src/lib.rs:2:17: 2:18";
+    assert_eq!(span(line), Some((25, "src/lib.rs", 2)));
+    let line = "        goto -> bb1; // scope 2 at C:\\src\\lib.rs:3:5: 5:6";
+    assert_eq!(span(line), Some((20, "C:\\src\\lib.rs", 3)));
+    assert_eq!(span("    bb1: {"), None);
+}
+
+#[test]
+fn test_resolve() {
+    let dir = std::env::temp_dir().join(format!("cargo-show-asm-resolve-{}", std::process::id()));
+    let (workspace, dep) = (dir.join("app"), dir.join("dep"));
+    std::fs::create_dir_all(workspace.join("src")).unwrap();
+    std::fs::create_dir_all(dep.join("src")).unwrap();
+    std::fs::write(workspace.join("src/main.rs"), "").unwrap();
+    std::fs::write(dep.join("src/lib.rs"), "").unwrap();
+    let roots = [workspace.clone(), dep.clone()];
+
+    assert_eq!(
+        resolve("src/main.rs", &roots),
+        workspace.join("src/main.rs")
+    );
+    assert_eq!(resolve("src/lib.rs", &roots), dep.join("src/lib.rs"));
+    // missing files are reported relative to the workspace
+    assert_eq!(
+        resolve("src/gone.rs", &roots),
+        workspace.join("src/gone.rs")
+    );
+    let std = "/rustc/e50aa6fba4e63ab34c72bf9acfd2c307c1155d1a/library/core/src/ptr/mod.rs";
+    assert_eq!(resolve(std, &roots), PathBuf::from(std));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_items_for_line() {
+    // `pub fn f(x:u32)->u32{mirdep::add(x,1)}` with `mirdep::add` inlined
+    let mir = "\
+fn f(_1: u32) -> u32 {
+    debug x => _1;                       // in scope 0 at src/lib.rs:1:10: 1:11
+    let mut _0: u32;                     // return place in scope 0 at src/lib.rs:1:18: 1:21
+    scope 1 (inlined mirdep::add) {      // at src/lib.rs:1:22: 1:38
+    }
+
+    bb0: {
+        _0 = BitXor(copy _1, const 1_u32); // scope 1 at /tmp/mirdep/src/lib.rs:2:5: 2:10
+        return;                          // scope 0 at src/lib.rs:1:39: 1:39
+    }
+}
+";
+    let lines = CachedLines::without_ending(mir.to_owned());
+    let items = find_items(&lines);
+    let strs = lines.iter().collect::<Vec<_>>();
+    let roots = [PathBuf::from("/tmp/mirapp")];
+    let names = |path: &str, line| {
+        items_for_line(&strs, &items, &roots, Path::new(path), line)
+            .into_iter()
+            .map(|i| i.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("src/lib.rs", 1), ["fn f(_1: u32) -> u32"]);
+    assert_eq!(names("mirdep/src/lib.rs", 2), ["fn f(_1: u32) -> u32"]);
+    assert!(names("mirdep/src/lib.rs", 1).is_empty());
+    assert!(names("src/main.rs", 1).is_empty());
+}
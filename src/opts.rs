@@ -1,7 +1,7 @@
 use bpaf::{construct, doc::Style, long, short, Bpaf, Parser};
 use cargo_metadata::Artifact;
-use std::path::PathBuf;
-
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 fn check_target_dir(path: PathBuf) -> anyhow::Result<PathBuf> {
     if path.is_dir() {
@@ -32,10 +32,29 @@ pub struct Options {
     #[bpaf(external)]
     pub select_fragment: SelectFragment,
 
+    /// Analyze an existing .s, .ll or .mir file, object file, library or executable instead
+    /// of building a crate, - reads stdin
+    ///
+    /// Output format is picked by the file extension unless it is given explicitly
+    #[bpaf(argument("PATH"), hide_usage)]
+    pub file: Option<PathBuf>,
+
+    /// Compile a single Rust file as a library with rustc instead of building a crate
+    #[bpaf(argument("PATH"), hide_usage)]
+    pub rs: Option<PathBuf>,
+
+    /// Rust edition of the file passed to --rs
+    #[bpaf(argument("EDITION"), fallback("2021".to_owned()), display_fallback, hide_usage)]
+    pub edition: String,
+
     // how to compile
     #[bpaf(external)]
     pub cargo: Cargo,
 
+    // what to compare against
+    #[bpaf(external)]
+    pub diff: Diff,
+
     /// Pass parameter to llvm-mca for mca targets
     #[bpaf(short('M'), long)]
     pub mca_arg: Vec<String>,
@@ -46,8 +65,9 @@ pub struct Options {
     pub target_cpu: Option<String>,
     #[bpaf(external)]
     pub format: Format,
-    #[bpaf(external)]
-    pub syntax: Syntax,
+    /// Output format, `None` unless given explicitly, use [`Options::syntax`] instead
+    #[bpaf(external(syntax), optional)]
+    pub syntax: Option<Syntax>,
 
     #[cfg(feature = "ipc")]
     #[bpaf(external, optional, hide_usage, hide)]
@@ -58,6 +78,17 @@ pub struct Options {
     pub to_dump: ToDump,
 }
 
+impl Options {
+    /// Output format, Intel assembly unless given explicitly or picked by `--file` extension
+    #[must_use]
+    pub fn syntax(&self) -> Syntax {
+        match &self.file {
+            Some(file) => Syntax::for_file(self.syntax, file),
+            None => self.syntax.unwrap_or(Syntax::Intel),
+        }
+    }
+}
+
 /// Retrieve as client:
 #[cfg(feature = "ipc")]
 #[derive(Bpaf, Clone, Debug)]
@@ -125,12 +156,80 @@ pub struct Cargo {
     /// Codegen flags to rustc, see 'rustc -C help' for details
     #[bpaf(short('C'), argument("FLAG"))]
     pub codegen: Vec<String>,
+    /// Keep codegen-units from the profile instead of using one, code from all the units is
+    /// merged and functions defined in several units are tagged with the unit name
+    #[bpaf(hide_usage)]
+    pub keep_codegen_units: bool,
+    /// Keep LTO settings from the profile and show code after link time optimization,
+    /// needs a target that gets linked. Pass `-C lto=fat` if the profile doesn't enable LTO
+    #[bpaf(hide_usage)]
+    pub lto: bool,
     /// Unstable (nightly-only) flags to Cargo, see 'cargo -Z help' for details
     // OsString would be better but MetadataCommand takes a vector of strings...
     #[bpaf(short('Z'), argument("FLAG"))]
     pub unstable: Vec<String>,
 }
 
+#[derive(Debug, Clone, Bpaf)]
+/// Compare with a second build, those options replace ones used for the first build:
+#[bpaf(hide_usage)]
+pub struct Diff {
+    /// Generate code for a specific CPU in the second build
+    #[bpaf(argument("CPU"), hide_usage)]
+    pub diff_target_cpu: Option<String>,
+    /// Build for this specific profile in the second build
+    #[bpaf(argument("PROFILE"), hide_usage)]
+    pub diff_profile: Option<String>,
+    /// A feature to activate in the second build, can be used multiple times
+    #[bpaf(argument("FEATURE"), hide_usage)]
+    pub diff_features: Vec<String>,
+    /// Codegen flag to rustc in the second build, can be used multiple times
+    #[bpaf(argument("FLAG"), hide_usage)]
+    pub diff_codegen: Vec<String>,
+}
+
+impl Diff {
+    /// No second build is requested
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.diff_target_cpu.is_none()
+            && self.diff_profile.is_none()
+            && self.diff_features.is_empty()
+            && self.diff_codegen.is_empty()
+    }
+
+    /// Cargo options and target CPU for the second build
+    ///
+    /// Second build uses a separate target directory inside of `target_dir`
+    /// so two builds don't overwrite each other's files
+    #[must_use]
+    pub fn apply(
+        &self,
+        cargo: &Cargo,
+        target_cpu: Option<&str>,
+        target_dir: &Path,
+    ) -> (Cargo, Option<String>) {
+        let mut cargo = cargo.clone();
+        let target_dir = cargo.target_dir.as_deref().unwrap_or(target_dir);
+        cargo.target_dir = Some(target_dir.join("asm-diff"));
+        if let Some(profile) = &self.diff_profile {
+            cargo.compile_mode = CompileMode::Custom(profile.clone());
+        }
+        if !self.diff_features.is_empty() {
+            cargo.cli_features.features = self.diff_features.clone();
+        }
+        if !self.diff_codegen.is_empty() {
+            cargo.codegen = self.diff_codegen.clone();
+        }
+        let target_cpu = self
+            .diff_target_cpu
+            .as_deref()
+            .or(target_cpu)
+            .map(ToOwned::to_owned);
+        (cargo, target_cpu)
+    }
+}
+
 #[derive(Debug, Clone, Bpaf)]
 /// Pick item to display from the artifact
 #[bpaf(fallback(ToDump::Unspecified))]
@@ -146,7 +245,24 @@ pub enum ToDump {
     },
 
     Function {
-        /// Dump a function with a given name, filter functions by name
+        #[bpaf(external(with_callees))]
+        with_callees: Option<usize>,
+
+        /// Treat FUNCTION as a regular expression instead of a part of the name
+        #[bpaf(hide_usage)]
+        regex: bool,
+
+        /// Match FUNCTION against the whole name, with or without the hash
+        #[bpaf(hide_usage)]
+        exact: bool,
+
+        /// Dump all the functions matching FUNCTION one after another
+        #[bpaf(hide_usage)]
+        all_matches: bool,
+
+        /// Dump a function with a given name, filter functions by name,
+        /// `src/lib.rs:120` picks functions with code from that line instead,
+        /// MIR needs a nightly compiler for that
         #[bpaf(positional("FUNCTION"))]
         function: String,
 
@@ -159,10 +275,86 @@ pub enum ToDump {
     #[bpaf(short('i'), long)]
     Interactive,
 
+    /// Show a summary for the whole file instead of a single function
+    Report {
+        /// Report to show, `sizes` lists code size for functions grouped by name,
+        /// `calls` prints call graph in Graphviz DOT format, `vtables` lists vtables
+        /// defined in the file
+        #[bpaf(long("report"), argument("REPORT"))]
+        report: Report,
+
+        /// Order for the report: `total` (default), `copies` or `name`
+        #[bpaf(argument("ORDER"), fallback(SortBy::Total))]
+        sort_by: SortBy,
+    },
+
     #[bpaf(skip)]
     Unspecified,
 }
 
+impl ToDump {
+    /// Rust source location passed instead of a function name: `src/lib.rs:120`
+    #[must_use]
+    pub fn source_location(&self) -> Option<(&Path, u64)> {
+        let ToDump::Function { function, .. } = self else {
+            return None;
+        };
+        let (path, line) = function.rsplit_once(':')?;
+        let path = Path::new(path);
+        if path.extension()? != "rs" {
+            return None;
+        }
+        Some((path, line.parse().ok()?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Report {
+    /// Number of instructions for every function, monomorphizations are grouped together
+    Sizes,
+    /// Call graph for all the functions in Graphviz DOT format
+    Calls,
+    /// Vtables with their methods
+    Vtables,
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sizes" => Ok(Self::Sizes),
+            "calls" => Ok(Self::Calls),
+            "vtables" => Ok(Self::Vtables),
+            _ => Err(format!(
+                "Unknown report {s:?}, expected one of: sizes, calls, vtables"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortBy {
+    Total,
+    Copies,
+    Name,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total" => Ok(Self::Total),
+            "copies" => Ok(Self::Copies),
+            "name" => Ok(Self::Name),
+            _ => Err(format!(
+                "Unknown order {s:?}, expected one of: total, copies, name"
+            )),
+        }
+    }
+}
+
 fn target_cpu() -> impl Parser<Option<String>> {
     let native = long("native")
         .help("Optimize for the CPU running the compiler")
@@ -212,6 +404,18 @@ fn verbosity() -> impl Parser<usize> {
         .hide_usage()
 }
 
+fn with_callees() -> impl Parser<Option<usize>> {
+    let depth = long("with-callees")
+        .help(
+            "Also dump functions defined in the same file the function calls, \
+            directly or via other functions up to DEPTH calls deep, no limit by default",
+        )
+        .argument::<usize>("DEPTH")
+        .adjacent();
+    let unlimited = long("with-callees").req_flag(usize::MAX).hide();
+    construct!([depth, unlimited]).optional().hide_usage()
+}
+
 fn manifest_path() -> impl Parser<PathBuf> {
     long("manifest-path")
         .help("Path to Cargo.toml, defaults to one in current folder")
@@ -232,9 +436,13 @@ fn manifest_path() -> impl Parser<PathBuf> {
 #[derive(Debug, Clone, Bpaf)]
 /// Postprocessing options:
 pub struct Format {
-    /// Print interleaved Rust code
+    /// Print interleaved Rust code, MIR needs a nightly compiler for that
     pub rust: bool,
 
+    /// Show functions the code was inlined through next to interleaved Rust code
+    #[bpaf(hide_usage)]
+    pub inlined: bool,
+
     #[bpaf(external(color_detection), hide_usage)]
     pub color: bool,
 
@@ -250,12 +458,93 @@ pub struct Format {
     #[bpaf(external)]
     pub verbosity: usize,
 
-    /// Try to strip some of the non-assembly instruction information
+    /// Try to strip some of the non-assembly instruction information,
+    /// span comments for MIR
     pub simplify: bool,
+
+    /// Show values of constants and string literals instructions refer to
+    #[bpaf(hide_usage)]
+    pub constants: bool,
+
+    /// Show methods called through vtables next to indirect calls, calls that can't be
+    /// matched to a vtable are marked as guesses
+    #[bpaf(hide_usage)]
+    pub dyn_calls: bool,
+
+    /// Draw arrows from jumps to their targets left of the code
+    #[bpaf(hide_usage)]
+    pub visualize_jumps: bool,
+
+    /// Mark loops and their nesting depth left of the code
+    #[bpaf(hide_usage)]
+    pub loops: bool,
+
+    /// Show only the innermost loops of the function, with --mca-* analyze only them
+    #[bpaf(hide_usage)]
+    pub inner_loops: bool,
+
+    /// Produce machine readable JSON output instead of colored text
+    #[bpaf(hide_usage)]
+    pub json: bool,
+
+    /// Compare the output against a snapshot in FILE and fail if it changed
+    #[bpaf(argument("FILE"), hide_usage)]
+    pub check: Option<PathBuf>,
+
+    /// Update the snapshot passed with --check instead of comparing
+    #[bpaf(hide_usage)]
+    pub bless: bool,
+
+    /// Something to show instead of the code, at most one
+    #[bpaf(external(mode), optional, hide_usage)]
+    pub mode: Option<Mode>,
+}
+
+impl Format {
+    /// Output is meant for other programs and shouldn't contain color escape sequences
+    #[must_use]
+    pub fn is_machine_readable(&self) -> bool {
+        self.json || self.mode == Some(Mode::Cfg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Bpaf)]
+/// Show something else instead of the code:
+pub enum Mode {
+    /// List calls to panicking functions instead of the code, fail if there are any
+    Panics,
+    /// Print control flow graph of the function in Graphviz DOT format
+    Cfg,
+    /// List functions called by the function instead of the code
+    Callees,
+    /// List functions calling the function instead of the code
+    Callers,
+    /// Print Rust source of the function with instructions generated for each line under it
+    BySource,
+    /// Count vector and scalar instructions and show scalar code inside of loops
+    SimdReport,
+    /// Show a histogram of opcodes, memory accesses, branches, calls and the stack frame size
+    Stats,
+}
+
+impl Mode {
+    /// Command line flag that selects the mode, for error messages
+    #[must_use]
+    pub fn flag(self) -> &'static str {
+        match self {
+            Self::Panics => "--panics",
+            Self::Cfg => "--cfg",
+            Self::Callees => "--callees",
+            Self::Callers => "--callers",
+            Self::BySource => "--by-source",
+            Self::SimdReport => "--simd-report",
+            Self::Stats => "--stats",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Bpaf, Eq, PartialEq, Copy)]
-#[bpaf(custom_usage(&[("OUTPUT-FORMAT", Style::Metavar)]), fallback(Syntax::Intel))]
+#[bpaf(custom_usage(&[("OUTPUT-FORMAT", Style::Metavar)]))]
 /// Pick output format:
 pub enum Syntax {
     /// Show assembly using Intel style
@@ -276,6 +565,9 @@ pub enum Syntax {
     McaIntel,
     /// Show llvm-mca analysis, AT&T style asm
     McaAtt,
+    /// Show machine code disassembled from the object file, with addresses and encoded bytes,
+    /// needs `disasm` feature
+    Disasm,
 }
 
 impl Syntax {
@@ -285,7 +577,7 @@ impl Syntax {
             Self::Intel | Self::McaIntel => Some("llvm-args=-x86-asm-syntax=intel"),
             Self::Att | Self::McaAtt => Some("llvm-args=-x86-asm-syntax=att"),
             Self::LlvmInput => Some("no-prepopulate-passes"),
-            Self::Wasm | Self::Mir | Self::Llvm => None,
+            Self::Wasm | Self::Mir | Self::Llvm | Self::Disasm => None,
         }
     }
 
@@ -295,6 +587,28 @@ impl Syntax {
             Self::Intel | Self::Att | Self::Wasm | Self::McaIntel | Self::McaAtt => "asm",
             Self::Llvm | Self::LlvmInput => "llvm-ir",
             Self::Mir => "mir",
+            Self::Disasm => "obj",
+        }
+    }
+
+    /// Output format for an existing file, `.ll` and `.mir` files are detected by extension
+    /// unless some format is requested explicitly, object files, libraries and
+    /// executables without an extension are disassembled, the latter only with the `disasm`
+    /// feature, otherwise such files are taken for assembly
+    #[must_use]
+    pub fn for_file(explicit: Option<Self>, path: &Path) -> Self {
+        if let Some(syntax) = explicit {
+            return syntax;
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ll") => Self::Llvm,
+            Some("mir") => Self::Mir,
+            Some("o" | "obj" | "rlib" | "a" | "lib" | "so" | "dylib" | "dll" | "exe") => {
+                Self::Disasm
+            }
+            #[cfg(feature = "disasm")]
+            None if path != Path::new("-") => Self::Disasm,
+            _ => Self::Intel,
         }
     }
 
@@ -304,6 +618,7 @@ impl Syntax {
             Self::Intel | Self::McaAtt | Self::McaIntel | Self::Att | Self::Wasm => "s",
             Self::Llvm | Self::LlvmInput => "ll",
             Self::Mir => "mir",
+            Self::Disasm => "o",
         }
     }
 }
@@ -419,6 +734,18 @@ impl Focus {
     }
 }
 
+/// LTO only happens when a crate gets linked: executables, tests and libraries
+/// meant to be used outside of Rust
+#[must_use]
+pub fn gets_linked(kinds: &[String]) -> bool {
+    kinds.iter().any(|kind| {
+        matches!(
+            kind.as_str(),
+            "bin" | "example" | "test" | "bench" | "cdylib" | "staticlib" | "dylib"
+        )
+    })
+}
+
 #[cfg(unix)]
 #[cfg(test)]
 fn write_updated(new_val: &str, path: impl AsRef<std::path::Path>) -> std::io::Result<bool> {
@@ -428,6 +755,7 @@ fn write_updated(new_val: &str, path: impl AsRef<std::path::Path>) -> std::io::R
         .write(true)
         .read(true)
         .create(true)
+        .truncate(false)
         .open(path)?;
     let mut current_val = String::new();
     file.read_to_string(&mut current_val)?;
@@ -449,3 +777,48 @@ fn docs_are_up_to_date() {
     let docs = readme.replacen("<USAGE>", &usage, 1);
     assert!(write_updated(&docs, "README.md").unwrap());
 }
+
+#[test]
+fn syntax_for_file() {
+    let syntax = |args: &[&str]| options().run_inner(args).unwrap().syntax();
+    assert_eq!(syntax(&["--file", "foo.s"]), Syntax::Intel);
+    assert_eq!(syntax(&["--file", "foo.ll"]), Syntax::Llvm);
+    assert_eq!(syntax(&["--file", "foo.mir"]), Syntax::Mir);
+    assert_eq!(syntax(&["--file", "libfoo.rlib"]), Syntax::Disasm);
+    #[cfg(feature = "disasm")]
+    assert_eq!(syntax(&["--file", "target/release/foo"]), Syntax::Disasm);
+    #[cfg(not(feature = "disasm"))]
+    assert_eq!(syntax(&["--file", "target/release/foo"]), Syntax::Intel);
+    // stdin has no extension to go by
+    assert_eq!(syntax(&["--file", "-"]), Syntax::Intel);
+    assert_eq!(syntax(&["--file", "-", "--llvm"]), Syntax::Llvm);
+    // explicit format wins, even if it's the default one
+    assert_eq!(syntax(&["--file", "foo.ll", "--intel"]), Syntax::Intel);
+    assert_eq!(syntax(&["--file", "foo.o", "--att"]), Syntax::Att);
+    assert_eq!(syntax(&[]), Syntax::Intel);
+    assert_eq!(syntax(&["--mir"]), Syntax::Mir);
+}
+
+#[test]
+fn linked_targets() {
+    let linked =
+        |kinds: &[&str]| gets_linked(&kinds.iter().map(|k| (*k).to_owned()).collect::<Vec<_>>());
+    assert!(linked(&["bin"]));
+    assert!(linked(&["example"]));
+    assert!(linked(&["test"]));
+    assert!(linked(&["lib", "cdylib"]));
+    assert!(linked(&["staticlib"]));
+    assert!(!linked(&["lib"]));
+    assert!(!linked(&["rlib"]));
+    assert!(!linked(&["proc-macro"]));
+}
+
+#[test]
+fn one_mode_at_a_time() {
+    let mode = |args: &[&str]| options().run_inner(args).map(|opts| opts.format.mode);
+    assert_eq!(mode(&[]).unwrap(), None);
+    assert_eq!(mode(&["--cfg"]).unwrap(), Some(Mode::Cfg));
+    assert_eq!(mode(&["--by-source"]).unwrap(), Some(Mode::BySource));
+    assert!(mode(&["--panics", "--cfg"]).is_err());
+    assert!(mode(&["--stats", "--simd-report"]).is_err());
+}
@@ -0,0 +1,127 @@
+//! Summaries for the whole file rather than a single function
+use std::collections::BTreeMap;
+
+use owo_colors::OwoColorize;
+
+use crate::{
+    color, dump_json,
+    opts::{Format, SortBy},
+    safeprintln, Item,
+};
+
+/// Code size for all the copies of a function with the same name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeRow<'a> {
+    pub name: &'a str,
+    pub total: usize,
+    pub copies: usize,
+}
+
+/// Group items by name, `items` contains item and its size in instructions
+#[must_use]
+pub fn group_sizes<'a>(
+    items: impl IntoIterator<Item = (&'a Item, usize)>,
+    sort_by: SortBy,
+) -> Vec<SizeRow<'a>> {
+    let mut groups = BTreeMap::new();
+    for (item, size) in items {
+        let row = groups.entry(item.name.as_str()).or_insert(SizeRow {
+            name: &item.name,
+            total: 0,
+            copies: 0,
+        });
+        row.total += size;
+        row.copies += 1;
+    }
+
+    // BTreeMap is already sorted by name, sort is stable so ties stay sorted by name
+    let mut rows = groups.into_values().collect::<Vec<_>>();
+    match sort_by {
+        SortBy::Total => rows.sort_by_key(|r| std::cmp::Reverse(r.total)),
+        SortBy::Copies => rows.sort_by_key(|r| std::cmp::Reverse(r.copies)),
+        SortBy::Name => {}
+    }
+    rows
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(part: usize, total: usize) -> String {
+    if total == 0 {
+        String::from("(0.0%)")
+    } else {
+        format!("({:.1}%)", part as f64 * 100.0 / total as f64)
+    }
+}
+
+/// Print a table with code sizes grouped by function name, similar to `cargo-llvm-lines`
+pub fn dump_sizes<'a>(
+    items: impl IntoIterator<Item = (&'a Item, usize)>,
+    sort_by: SortBy,
+    fmt: &Format,
+) -> anyhow::Result<()> {
+    let rows = group_sizes(items, sort_by);
+    let total = rows.iter().map(|r| r.total).sum::<usize>();
+    let copies = rows.iter().map(|r| r.copies).sum::<usize>();
+
+    if fmt.json {
+        let rows = rows
+            .iter()
+            .map(|r| serde_json::json!({ "name": r.name, "total": r.total, "copies": r.copies }))
+            .collect::<Vec<_>>();
+        let body = serde_json::json!({ "total": total, "copies": copies, "functions": rows });
+        return dump_json(None, body);
+    }
+
+    safeprintln!("  Instructions          Copies        Function name");
+    safeprintln!("  ------------          ------        -------------");
+    safeprintln!(
+        "{:>14} {:<8}{:>6} {:<8}  (TOTAL)",
+        total,
+        percent(total, total),
+        copies,
+        percent(copies, copies)
+    );
+    for row in &rows {
+        safeprintln!(
+            "{:>14} {:<8}{:>6} {:<8}  {}",
+            color!(row.total, OwoColorize::cyan),
+            percent(row.total, total),
+            color!(row.copies, OwoColorize::cyan),
+            percent(row.copies, copies),
+            color!(row.name, OwoColorize::green),
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_group_sizes() {
+    let item = |name: &str, index| Item {
+        name: name.to_owned(),
+        hashed: format!("{name}::h{index}"),
+        index,
+        len: 0,
+    };
+    let items = [
+        item("alloc::vec::Vec<T,A>::push", 0),
+        item("alloc::vec::Vec<T,A>::push", 1),
+        item("core::fmt::write", 0),
+        item("main", 0),
+    ];
+    let sizes = [10, 20, 100, 5];
+
+    let rows = group_sizes(items.iter().zip(sizes), SortBy::Total);
+    let names = rows.iter().map(|r| r.name).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["core::fmt::write", "alloc::vec::Vec<T,A>::push", "main"]
+    );
+    assert_eq!(rows[1].total, 30);
+    assert_eq!(rows[1].copies, 2);
+
+    let rows = group_sizes(items.iter().zip(sizes), SortBy::Copies);
+    assert_eq!(rows[0].name, "alloc::vec::Vec<T,A>::push");
+
+    let rows = group_sizes(items.iter().zip(sizes), SortBy::Name);
+    assert_eq!(rows[2].name, "main");
+}
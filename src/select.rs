@@ -143,7 +143,7 @@ impl SelectProcess<'_> {
                 Finder::Custom { preview, .. } => {
                     for &arg in preview {
                         if arg == "PREVIEWSERVER" {
-                            self.cmd.arg(&ipc::get_address());
+                            self.cmd.arg(ipc::get_address());
                         } else {
                             self.cmd.arg(arg);
                         }